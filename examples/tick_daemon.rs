@@ -1,5 +1,4 @@
-use daemon_forge::ForgeDaemon;
-use std::fs::OpenOptions;
+use daemon_forge::{ForgeDaemon, Stdio};
 use std::thread;
 use std::time::Duration;
 use std::env;
@@ -16,17 +15,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let pid_path = pwd.join("ticker.pid");
 
     // 2. Abrir archivos de log (Append mode)
-    let stdout_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&log_path)
-        .expect("Couldn't open stdout");
-
-    let stderr_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&err_path)
-        .expect("Couldn't open stderr");
+    let stdout_file = Stdio::append(&log_path).expect("Couldn't open stdout");
+    let stderr_file = Stdio::append(&err_path).expect("Couldn't open stderr");
 
     println!("Launching a simple ticker Daemon...");
     println!("Logs will be written to: {:?}", log_path);
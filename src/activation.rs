@@ -0,0 +1,66 @@
+//! Systemd socket activation: descriptors passed in `LISTEN_FDS`, named by `LISTEN_FDNAMES`.
+
+use crate::error::{DaemonError, DaemonResult};
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::sync::Mutex;
+
+/// First descriptor passed by systemd (`SD_LISTEN_FDS_START`).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Descriptors handed out by `listen_fds_with_names`, expected by `assert_clean_fds`.
+static TAKEN_FDS: Mutex<Vec<RawFd>> = Mutex::new(Vec::new());
+
+/// (Unix) Takes the sockets passed by systemd socket activation, each paired with its
+/// name from `LISTEN_FDNAMES` (the `FileDescriptorName=` of its `.socket` unit).
+///
+/// Returns an empty list when nothing was passed, or when `LISTEN_PID` names another
+/// process. Call it before `start()`: systemd addresses the descriptors to the launcher's
+/// PID, and the forked daemon inherits them. The variables are removed, so a second call
+/// returns nothing and programs the daemon spawns don't see them; the descriptors are
+/// marked close-on-exec. A name list that doesn't match the descriptor count is ignored.
+/// Fails with `DaemonError::EnvError` on malformed variables or a descriptor that isn't open.
+pub fn listen_fds_with_names() -> DaemonResult<Vec<(Option<String>, OwnedFd)>> {
+    let pid = std::env::var("LISTEN_PID").ok();
+    let count = std::env::var("LISTEN_FDS").ok();
+    let names = std::env::var("LISTEN_FDNAMES").ok();
+    for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        unsafe { std::env::remove_var(var) };
+    }
+
+    let Some(count) = count else {
+        return Ok(Vec::new());
+    };
+    if let Some(pid) = pid {
+        let pid = pid.trim().parse::<u32>()
+            .map_err(|_| DaemonError::EnvError(format!("LISTEN_PID is not a valid PID: {:?}", pid)))?;
+        if pid != std::process::id() {
+            return Ok(Vec::new());
+        }
+    }
+    let count = count.trim().parse::<RawFd>()
+        .ok()
+        .filter(|n| *n >= 0)
+        .ok_or_else(|| DaemonError::EnvError(format!("LISTEN_FDS is not a valid count: {:?}", count)))?;
+
+    let names: Vec<&str> = names.as_deref().map(|n| n.split(':').collect()).unwrap_or_default();
+    let named = names.len() == count as usize;
+
+    let mut fds = Vec::with_capacity(count as usize);
+    for (i, fd) in (LISTEN_FDS_START..LISTEN_FDS_START + count).enumerate() {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        if flags < 0 {
+            return Err(DaemonError::EnvError(format!("LISTEN_FDS descriptor {} is not open", fd)));
+        }
+        unsafe { libc::fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC) };
+        let name = names.get(i).filter(|n| named && !n.is_empty()).map(|n| n.to_string());
+        fds.push((name, unsafe { OwnedFd::from_raw_fd(fd) }));
+    }
+    TAKEN_FDS.lock().unwrap_or_else(|e| e.into_inner()).extend(LISTEN_FDS_START..LISTEN_FDS_START + count);
+    Ok(fds)
+}
+
+/// Descriptors taken with `listen_fds_with_names`.
+#[cfg(target_os = "linux")]
+pub(crate) fn taken_fds() -> Vec<RawFd> {
+    TAKEN_FDS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
@@ -1,10 +1,14 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::time::Duration;
 use crate::{User, Group}; // Required for UNIX
 use std::io::Write; 
 use std::fmt; 
 use crate::stdio::Stdio;
-use crate::error::{DaemonResult, DaemonError};
+use crate::types::{DaemonStatus, EnvPrecedence, IoClass, LockStyle, RelaunchMode, SigpipeMode, StartState};
+use crate::watchdog::WatchdogAction;
+use crate::error::{ConfigProblem, DaemonResult, DaemonError};
 
 /// Main constructor to configure and launch the daemon process.
 ///
@@ -17,6 +21,24 @@ pub struct ForgeDaemon<SetupOutput> {
     pub(crate) stdin: Stdio,
     pub(crate) stdout: Stdio,
     pub(crate) stderr: Stdio,
+    pub(crate) pid_dir_create: bool,
+    pub(crate) required_env: Vec<String>,
+    pub(crate) child_args: Option<Vec<String>>,
+    pub(crate) before_fork: Option<Box<dyn FnOnce() -> DaemonResult<()>>>,
+    pub(crate) leader_election: bool,
+    pub(crate) launcher_exit_on_failure: bool,
+    pub(crate) env_precedence: EnvPrecedence,
+    pub(crate) inherited_env: HashMap<String, String>,
+    pub(crate) exe_sha256: Option<String>,
+    pub(crate) on_ready: Option<Box<dyn FnOnce() -> DaemonResult<()>>>,
+    pub(crate) hang_watchdog: Option<(std::time::Duration, WatchdogAction)>,
+    pub(crate) inherit_env_deferred: bool,
+    pub(crate) dump_env_to: Option<PathBuf>,
+    pub(crate) additional_pid_files: Vec<PathBuf>,
+    pub(crate) create_log_dirs: bool,
+    pub(crate) allow_no_lock: bool,
+    pub(crate) on_setup_failure: Option<Box<dyn FnOnce() -> DaemonResult<()>>>,
+    pub(crate) internal_flag_name: Option<String>,
     
     // Environment Configuration
     pub(crate) clear_env: bool,
@@ -28,12 +50,70 @@ pub struct ForgeDaemon<SetupOutput> {
     #[cfg(unix)] pub(crate) umask: Option<u32>,
     #[cfg(unix)] pub(crate) root: Option<PathBuf>,
     #[cfg(unix)] pub(crate) chown_pid: bool,
+    #[cfg(unix)] pub(crate) launcher_linger: Option<Duration>,
+    #[cfg(unix)] pub(crate) verify_pid: bool,
+    #[cfg(unix)] pub(crate) pid_dir_mode: u32,
+    #[cfg(unix)] pub(crate) chroot_chdir: bool,
+    #[cfg(unix)] pub(crate) runtime_dir: Option<PathBuf>,
+    #[cfg(unix)] pub(crate) runtime_dir_setgid: bool,
+    #[cfg(unix)] pub(crate) pid_fallback_dir: Option<PathBuf>,
+    #[cfg(unix)] pub(crate) strict_devnull: bool,
+    #[cfg(unix)] pub(crate) core_dump_dir: Option<PathBuf>,
+    #[cfg(all(unix, feature = "pty"))] pub(crate) pty: bool,
+    #[cfg(unix)] pub(crate) detect_pid_tampering: bool,
+    #[cfg(unix)] pub(crate) block_signals_during_setup: bool,
+    #[cfg(unix)] pub(crate) logs_nofollow: bool,
+    #[cfg(unix)] pub(crate) profile_startup: bool,
+    #[cfg(unix)] pub(crate) relaunch_mode: RelaunchMode,
+    #[cfg(target_os = "linux")] pub(crate) oom_score_adj: Option<i32>,
+    #[cfg(unix)] pub(crate) on_reload: Option<Box<dyn FnMut() -> DaemonResult<()> + Send>>,
+    #[cfg(unix)] pub(crate) die_with_parent: bool,
+    #[cfg(windows)] pub(crate) inherit_handles: Vec<std::os::windows::io::RawHandle>,
+    #[cfg(unix)] pub(crate) tmp_dir: Option<PathBuf>,
+    #[cfg(unix)] pub(crate) reopen_stdio_on_reload: bool,
+    #[cfg(unix)] pub(crate) abort_if_root: bool,
+    #[cfg(unix)] pub(crate) require_user: bool,
+    #[cfg(unix)] pub(crate) lock_file: Option<PathBuf>,
+    #[cfg(unix)] pub(crate) container_aware: bool,
+    #[cfg(unix)] pub(crate) panic_to_syslog: bool,
+    #[cfg(unix)] pub(crate) capture_backtrace: bool,
+    #[cfg(unix)] pub(crate) sigpipe: SigpipeMode,
+    #[cfg(unix)] pub(crate) pid_file_mode: u32,
+    #[cfg(unix)] pub(crate) log_dir_mode: u32,
+    #[cfg(unix)] pub(crate) debug_signal: Option<i32>,
+    #[cfg(unix)] pub(crate) privilege_regainable: bool,
+    #[cfg(unix)] pub(crate) pid_sync_barrier: bool,
+    #[cfg(unix)] pub(crate) reexec_on_upgrade: Option<i32>,
+    #[cfg(unix)] pub(crate) lock_style: LockStyle,
+    #[cfg(target_os = "linux")] pub(crate) log_reserve_bytes: u64,
+    #[cfg(unix)] pub(crate) set_user_env: bool,
+    #[cfg(target_os = "linux")] pub(crate) assert_clean_fds: bool,
+    #[cfg(unix)] pub(crate) require_setsid: bool,
+    #[cfg(unix)] pub(crate) pid_heartbeat: Option<Duration>,
+    #[cfg(unix)] pub(crate) signal_pipe: Vec<i32>,
+    #[cfg(target_os = "linux")] pub(crate) ionice: Option<IoClass>,
+    #[cfg(unix)] pub(crate) socket_umask: Option<u32>,
+    #[cfg(unix)] pub(crate) on_shutdown: Option<crate::shutdown::ShutdownHook>,
+    #[cfg(unix)] pub(crate) close_fds: bool,
+    #[cfg(unix)] pub(crate) preserved_fds: Vec<i32>,
 
     // The action now returns a Result
     pub(crate) privileged_action: Option<Box<dyn FnOnce() -> DaemonResult<SetupOutput>>>,
 }
 
 // C-DEBUG: Implementación manual de Debug porque el closure no lo soporta.
+/// Formats environment variables with their values hidden: the `Debug` output ends up in
+/// logs (`debug_signal`), and `.env()` often carries secrets.
+struct RedactedEnv<'a>(&'a HashMap<String, String>);
+
+impl fmt::Debug for RedactedEnv<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut keys: Vec<&String> = self.0.keys().collect();
+        keys.sort();
+        f.debug_map().entries(keys.into_iter().map(|k| (k, format_args!("<redacted>")))).finish()
+    }
+}
+
 impl<T> fmt::Debug for ForgeDaemon<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut ds = f.debug_struct("ForgeDaemon");
@@ -43,8 +123,22 @@ impl<T> fmt::Debug for ForgeDaemon<T> {
           .field("stdin", &self.stdin)
           .field("stdout", &self.stdout)
           .field("stderr", &self.stderr)
+          .field("pid_dir_create", &self.pid_dir_create)
+          .field("required_env", &self.required_env)
+          .field("child_args", &self.child_args)
+          .field("leader_election", &self.leader_election)
+          .field("launcher_exit_on_failure", &self.launcher_exit_on_failure)
+          .field("env_precedence", &self.env_precedence)
+          .field("exe_sha256", &self.exe_sha256)
+          .field("hang_watchdog", &self.hang_watchdog)
+          .field("inherit_env_deferred", &self.inherit_env_deferred)
+          .field("dump_env_to", &self.dump_env_to)
+          .field("additional_pid_files", &self.additional_pid_files)
+          .field("create_log_dirs", &self.create_log_dirs)
+          .field("allow_no_lock", &self.allow_no_lock)
+          .field("internal_flag_name", &self.internal_flag_name)
           .field("clear_env", &self.clear_env)
-          .field("env_vars", &self.env_vars);
+          .field("env_vars", &RedactedEnv(&self.env_vars));
 
         #[cfg(unix)]
         {
@@ -52,15 +146,115 @@ impl<T> fmt::Debug for ForgeDaemon<T> {
               .field("group", &self.group)
               .field("umask", &self.umask)
               .field("root", &self.root)
-              .field("chown_pid", &self.chown_pid);
+              .field("chown_pid", &self.chown_pid)
+              .field("launcher_linger", &self.launcher_linger)
+              .field("verify_pid", &self.verify_pid)
+              .field("pid_dir_mode", &self.pid_dir_mode)
+              .field("chroot_chdir", &self.chroot_chdir)
+              .field("runtime_dir", &self.runtime_dir)
+              .field("runtime_dir_setgid", &self.runtime_dir_setgid)
+              .field("pid_fallback_dir", &self.pid_fallback_dir)
+              .field("strict_devnull", &self.strict_devnull)
+              .field("core_dump_dir", &self.core_dump_dir)
+              .field("detect_pid_tampering", &self.detect_pid_tampering)
+              .field("block_signals_during_setup", &self.block_signals_during_setup)
+              .field("logs_nofollow", &self.logs_nofollow)
+              .field("profile_startup", &self.profile_startup)
+              .field("relaunch_mode", &self.relaunch_mode)
+              .field("die_with_parent", &self.die_with_parent)
+              .field("tmp_dir", &self.tmp_dir)
+              .field("reopen_stdio_on_reload", &self.reopen_stdio_on_reload)
+              .field("abort_if_root", &self.abort_if_root)
+              .field("require_user", &self.require_user)
+              .field("lock_file", &self.lock_file)
+              .field("container_aware", &self.container_aware)
+              .field("panic_to_syslog", &self.panic_to_syslog)
+              .field("capture_backtrace", &self.capture_backtrace)
+              .field("sigpipe", &self.sigpipe)
+              .field("pid_file_mode", &self.pid_file_mode)
+              .field("log_dir_mode", &self.log_dir_mode)
+              .field("debug_signal", &self.debug_signal)
+              .field("privilege_regainable", &self.privilege_regainable)
+              .field("pid_sync_barrier", &self.pid_sync_barrier)
+              .field("reexec_on_upgrade", &self.reexec_on_upgrade)
+              .field("lock_style", &self.lock_style)
+              .field("set_user_env", &self.set_user_env)
+              .field("require_setsid", &self.require_setsid)
+              .field("pid_heartbeat", &self.pid_heartbeat)
+              .field("signal_pipe", &self.signal_pipe)
+              .field("socket_umask", &self.socket_umask)
+              .field("close_fds", &self.close_fds)
+              .field("preserved_fds", &self.preserved_fds);
         }
 
+        #[cfg(all(unix, feature = "pty"))]
+        ds.field("pty", &self.pty);
+        #[cfg(unix)]
+        ds.field("on_reload", &if self.on_reload.is_some() { "Some(FnMut)" } else { "None" })
+          .field("on_shutdown", &if self.on_shutdown.is_some() { "Some(FnOnce)" } else { "None" });
+        #[cfg(target_os = "linux")]
+        ds.field("oom_score_adj", &self.oom_score_adj)
+          .field("log_reserve_bytes", &self.log_reserve_bytes)
+          .field("assert_clean_fds", &self.assert_clean_fds)
+          .field("ionice", &self.ionice);
+        #[cfg(windows)]
+        ds.field("inherit_handles", &self.inherit_handles);
+
         // Indicamos que existe una acción, pero opaca
-        ds.field("privileged_action", &if self.privileged_action.is_some() { "Some(FnOnce)" } else { "None" })
+        ds.field("before_fork", &if self.before_fork.is_some() { "Some(FnOnce)" } else { "None" })
+          .field("on_ready", &if self.on_ready.is_some() { "Some(FnOnce)" } else { "None" })
+          .field("on_setup_failure", &if self.on_setup_failure.is_some() { "Some(FnOnce)" } else { "None" })
+          .field("privileged_action", &if self.privileged_action.is_some() { "Some(FnOnce)" } else { "None" })
           .finish()
     }
 }
 
+/// The resolved configuration of a `ForgeDaemon`, returned by `ForgeDaemon::into_parts()`
+/// for consumers that drive the fork/setup sequence themselves.
+///
+/// Paths and the environment are resolved the way `start()` would use them.
+#[non_exhaustive]
+pub struct DaemonParts<SetupOutput> {
+    /// Internal name of the daemon.
+    pub name: Option<String>,
+    /// Working directory to change into.
+    pub working_directory: PathBuf,
+    /// PID file path, including the name-based fallback (Unix). Relative paths are
+    /// relative to the working directory; on Unix, paths are as seen inside the `chroot`.
+    pub pid_file: Option<PathBuf>,
+    /// Standard input configuration.
+    pub stdin: Stdio,
+    /// Standard output configuration.
+    pub stdout: Stdio,
+    /// Standard error configuration.
+    pub stderr: Stdio,
+    /// Whether the inherited environment should be cleared.
+    pub clear_env: bool,
+    /// Variables to set, with `env_precedence` already applied.
+    pub env: HashMap<String, String>,
+    /// (Unix) Dedicated lock file, when the PID file is not the lock.
+    #[cfg(unix)]
+    pub lock_file: Option<PathBuf>,
+    /// (Unix) User to switch to.
+    #[cfg(unix)]
+    pub user: Option<User>,
+    /// (Unix) Group to switch to.
+    #[cfg(unix)]
+    pub group: Option<Group>,
+    /// (Unix) File creation mask.
+    #[cfg(unix)]
+    pub umask: Option<u32>,
+    /// (Unix) Chroot directory.
+    #[cfg(unix)]
+    pub root: Option<PathBuf>,
+    /// Hook to run in the original process before forking.
+    pub before_fork: Option<Box<dyn FnOnce() -> DaemonResult<()>>>,
+    /// Hook to run in the daemon when setup or the privileged action fails.
+    pub on_setup_failure: Option<Box<dyn FnOnce() -> DaemonResult<()>>>,
+    /// The privileged action.
+    pub privileged_action: Box<dyn FnOnce() -> DaemonResult<SetupOutput>>,
+}
+
 impl Default for ForgeDaemon<()> {
     fn default() -> Self { Self::new() }
 }
@@ -84,6 +278,24 @@ impl ForgeDaemon<()> {
             stdin: Stdio::devnull(),
             stdout: Stdio::devnull(),
             stderr: Stdio::devnull(),
+            pid_dir_create: false,
+            required_env: Vec::new(),
+            child_args: None,
+            before_fork: None,
+            leader_election: false,
+            launcher_exit_on_failure: false,
+            env_precedence: EnvPrecedence::ExplicitWins,
+            inherited_env: HashMap::new(),
+            exe_sha256: None,
+            on_ready: None,
+            hang_watchdog: None,
+            inherit_env_deferred: false,
+            dump_env_to: None,
+            additional_pid_files: Vec::new(),
+            create_log_dirs: false,
+            allow_no_lock: false,
+            on_setup_failure: None,
+            internal_flag_name: None,
             clear_env: false,
             env_vars: HashMap::new(),
 
@@ -92,10 +304,89 @@ impl ForgeDaemon<()> {
             #[cfg(unix)] umask: Some(0o027),
             #[cfg(unix)] root: None,
             #[cfg(unix)] chown_pid: false,
+            #[cfg(unix)] launcher_linger: None,
+            #[cfg(unix)] verify_pid: false,
+            #[cfg(unix)] pid_dir_mode: 0o755,
+            #[cfg(unix)] chroot_chdir: true,
+            #[cfg(unix)] runtime_dir: None,
+            #[cfg(unix)] runtime_dir_setgid: false,
+            #[cfg(unix)] pid_fallback_dir: None,
+            #[cfg(unix)] strict_devnull: true,
+            #[cfg(unix)] core_dump_dir: None,
+            #[cfg(all(unix, feature = "pty"))] pty: false,
+            #[cfg(unix)] detect_pid_tampering: false,
+            #[cfg(unix)] block_signals_during_setup: true,
+            #[cfg(unix)] logs_nofollow: true,
+            #[cfg(unix)] profile_startup: false,
+            #[cfg(unix)] relaunch_mode: RelaunchMode::Fork,
+            #[cfg(target_os = "linux")] oom_score_adj: None,
+            #[cfg(unix)] on_reload: None,
+            #[cfg(unix)] die_with_parent: false,
+            #[cfg(windows)] inherit_handles: Vec::new(),
+            #[cfg(unix)] tmp_dir: None,
+            #[cfg(unix)] reopen_stdio_on_reload: false,
+            #[cfg(unix)] abort_if_root: false,
+            #[cfg(unix)] require_user: false,
+            #[cfg(unix)] lock_file: None,
+            #[cfg(unix)] container_aware: false,
+            #[cfg(unix)] panic_to_syslog: false,
+            #[cfg(unix)] capture_backtrace: false,
+            #[cfg(unix)] sigpipe: SigpipeMode::Ignore,
+            #[cfg(unix)] pid_file_mode: 0o644,
+            #[cfg(unix)] log_dir_mode: 0o755,
+            #[cfg(unix)] debug_signal: None,
+            #[cfg(unix)] privilege_regainable: false,
+            #[cfg(unix)] pid_sync_barrier: false,
+            #[cfg(unix)] reexec_on_upgrade: None,
+            #[cfg(unix)] lock_style: LockStyle::Flock,
+            #[cfg(target_os = "linux")] log_reserve_bytes: 0,
+            #[cfg(unix)] set_user_env: false,
+            #[cfg(target_os = "linux")] assert_clean_fds: false,
+            #[cfg(unix)] require_setsid: true,
+            #[cfg(unix)] pid_heartbeat: None,
+            #[cfg(unix)] signal_pipe: Vec::new(),
+            #[cfg(target_os = "linux")] ionice: None,
+            #[cfg(unix)] socket_umask: None,
+            #[cfg(unix)] on_shutdown: None,
+            #[cfg(unix)] close_fds: false,
+            #[cfg(unix)] preserved_fds: Vec::new(),
 
             privileged_action: Some(Box::new(|| Ok(()))),
         }
     }
+
+    /// Returns the most recent startup failure DaemonForge logged to `stderr_path`.
+    ///
+    /// Scans the log for lines carrying the `[DaemonForge Critical]` marker and returns the
+    /// last one's message, without the marker and timestamp. `None` if the file can't be
+    /// read or holds no such line. Lets a `status` command report why the daemon failed.
+    pub fn last_error_from_log<P: AsRef<Path>>(stderr_path: P) -> Option<String> {
+        let content = std::fs::read(stderr_path).ok()?;
+        content
+            .rsplit(|&b| b == b'\n')
+            .find_map(|line| {
+                let line = String::from_utf8_lossy(line);
+                let rest = line.split_once(CRITICAL_MARKER)?.1.trim();
+                // Strip the `[<unix seconds>]` timestamp; older lines have none
+                let message = rest
+                    .strip_prefix('[')
+                    .and_then(|r| r.split_once("] "))
+                    .filter(|(ts, _)| ts.chars().all(|c| c.is_ascii_digit()))
+                    .map_or(rest, |(_, msg)| msg);
+                Some(message.to_owned())
+            })
+    }
+}
+
+/// Prefix of the error lines DaemonForge writes to the daemon's logs.
+pub(crate) const CRITICAL_MARKER: &str = "[DaemonForge Critical]";
+
+/// Formats `msg` as a log line: `[DaemonForge Critical] [<unix seconds>] <msg>`.
+pub(crate) fn critical_line(msg: &str) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    format!("{} [{}] {}", CRITICAL_MARKER, now, msg)
 }
 
 impl<SetupOutput> ForgeDaemon<SetupOutput> {
@@ -108,8 +399,66 @@ impl<SetupOutput> ForgeDaemon<SetupOutput> {
     /// Returns the configured PID file path, if any.
     pub fn pid_file_path(&self) -> Option<&Path> { self.pid_file.as_deref() }
     
+    /// Returns the absolute path of the PID file the daemon will write, if any.
+    ///
+    /// Unlike `pid_file_path()`, this applies the same resolution the daemon performs:
+    /// the name-based fallback in the temp directory (Unix), the working directory for
+    /// relative paths and the `chroot` jail. Useful for logging before `start()`.
+    pub fn effective_pid_path(&self) -> Option<PathBuf> {
+        let path = self.resolve_path(&self.lock_path()?);
+        if path.is_relative() {
+            std::env::current_dir().ok().map(|cwd| cwd.join(&path)).or(Some(path))
+        } else {
+            Some(path)
+        }
+    }
+
+    /// Returns the absolute path of the file holding the instance lock, if any: the
+    /// `lock_file` when configured, otherwise the PID file (`effective_pid_path()`).
+    ///
+    /// External tooling can take the same lock (of the configured `lock_style`) to
+    /// detect a running instance. Always `None` on Windows, which locks a named mutex
+    /// (see `windows_mutex_name()`).
+    pub fn effective_lock_path(&self) -> Option<PathBuf> {
+        #[cfg(unix)]
+        if let Some(lock_file) = &self.lock_file {
+            let path = self.resolve_path(lock_file);
+            return match path.is_relative() {
+                true => std::env::current_dir().ok().map(|cwd| cwd.join(&path)).or(Some(path)),
+                false => Some(path),
+            };
+        }
+
+        #[cfg(unix)]
+        return self.effective_pid_path();
+
+        #[cfg(not(unix))]
+        None
+    }
+
+    /// Returns the name of the global mutex that guards the single instance on Windows:
+    /// `Global\DaemonForge_<name>`, or, without a `name`, `Global\DaemonForge_` followed
+    /// by the 16-digit hexadecimal FNV-1a hash of the `pid_file` path as configured.
+    /// `None` if neither is set.
+    ///
+    /// Computed on every platform, so cross-platform tooling can derive it too. An external
+    /// watchdog can open the mutex (`OpenMutexW`) to detect the running service.
+    pub fn windows_mutex_name(&self) -> Option<String> {
+        if let Some(name) = &self.name {
+            Some(format!("Global\\DaemonForge_{}", name))
+        } else {
+            // Hash the path so long paths still fit the mutex name length limit
+            let path = self.pid_file.as_ref()?.to_string_lossy();
+            Some(format!("Global\\DaemonForge_{:016X}", fnv1a_64(path.as_bytes())))
+        }
+    }
+
     /// Returns a reference to the environment variables map.
     pub fn environment(&self) -> &HashMap<String, String> { &self.env_vars }
+
+    /// Returns a mutable reference to the environment variables map,
+    /// for inserting or removing variables outside the builder chain.
+    pub fn environment_mut(&mut self) -> &mut HashMap<String, String> { &mut self.env_vars }
     
     /// Returns the configured working directory.
     pub fn working_directory_path(&self) -> &Path { &self.directory }
@@ -124,6 +473,35 @@ impl<SetupOutput> ForgeDaemon<SetupOutput> {
     /// Sets the path to the PID file.
     /// This file is used for locking to ensure only one instance runs.
     pub fn pid_file<P: Into<PathBuf>>(mut self, path: P) -> Self { self.pid_file = Some(path.into()); self }
+
+    /// Writes the daemon's PID to each of `paths` as well, for tools that look for PID
+    /// files in other conventional locations.
+    ///
+    /// Only the primary `pid_file` holds the lock; the copies are informational, written
+    /// right after it and removed together with it. Resolved like the PID file.
+    pub fn additional_pid_files<P: AsRef<Path>>(mut self, paths: &[P]) -> Self {
+        self.additional_pid_files.extend(paths.iter().map(|p| p.as_ref().to_path_buf()));
+        self
+    }
+
+    /// (Unix) Holds the instance lock on a dedicated file instead of the PID file.
+    ///
+    /// The PID is still written to the `pid_file` (if any), but without a lock, so
+    /// other tools can read or rewrite it freely. Both files are resolved like the PID file.
+    #[cfg(unix)] pub fn lock_file<P: Into<PathBuf>>(mut self, path: P) -> Self { self.lock_file = Some(path.into()); self }
+    #[cfg(not(unix))] pub fn lock_file<P: Into<PathBuf>>(self, _: P) -> Self { self }
+
+    /// (Unix) Selects `flock` (default) or POSIX `fcntl` record locks for the instance lock.
+    ///
+    /// Use `LockStyle::Fcntl` when the PID file lives on NFS. POSIX locks belong to the
+    /// process rather than the descriptor: a second instance started from the same process
+    /// is not refused, and closing *any* descriptor of the locked file releases the lock,
+    /// so the daemon must not open and close the PID file itself (`health()` and
+    /// `detect_pid_tampering` read it through the locked descriptor). The lock survives
+    /// `reexec_on_upgrade`, whose descriptor stays open across `execve`; an `OwnedFd`
+    /// from `take_lock_fd()` holds it like a `flock`.
+    #[cfg(unix)] pub fn lock_style(mut self, style: LockStyle) -> Self { self.lock_style = style; self }
+    #[cfg(not(unix))] pub fn lock_style(self, _: LockStyle) -> Self { self }
     
     /// Sets the working directory for the daemon.
     pub fn working_directory<P: Into<PathBuf>>(mut self, path: P) -> Self { self.directory = path.into(); self }
@@ -136,8 +514,100 @@ impl<SetupOutput> ForgeDaemon<SetupOutput> {
     
     /// Configures the standard error stream.
     pub fn stderr<S: Into<Stdio>>(mut self, stdio: S) -> Self { self.stderr = stdio.into(); self }
+
+    /// If true, missing parent directories of `Stdio::file` log paths are created before
+    /// forking, while still privileged.
+    ///
+    /// On Unix, new directories get the `log_dir_mode` and are owned by the configured
+    /// `user`/`group`, so the daemon can still reopen its logs after dropping privileges.
+    /// Existing directories are left untouched.
+    pub fn create_log_dirs(mut self, create: bool) -> Self { self.create_log_dirs = create; self }
+
+    /// (Unix) Sets the mode of the directories created by `create_log_dirs`, intermediate
+    /// ones included. Defaults to `0o755`.
+    ///
+    /// Ignored on Windows, where new directories inherit the parent's ACL.
+    #[cfg(unix)] pub fn log_dir_mode(mut self, mode: u32) -> Self { self.log_dir_mode = mode; self }
+    #[cfg(not(unix))] pub fn log_dir_mode(self, _: u32) -> Self { self }
+
+    /// Sends both stdout and stderr to `stdio`, through a single open file.
+    ///
+    /// Unlike configuring both streams with the same path, output keeps its order and
+    /// the file is opened once: stderr is a duplicate of stdout (`dup2` on Unix, a cloned
+    /// handle on Windows). Shorthand for `.stdout(stdio).stderr(Stdio::merge())`.
+    pub fn merge_stdout_stderr<S: Into<Stdio>>(mut self, stdio: S) -> Self {
+        self.stdout = stdio.into();
+        self.stderr = Stdio::SameAsStdout;
+        self
+    }
     
+    /// If `false`, a `/dev/null` that can't be opened no longer aborts startup.
+    ///
+    /// (Unix) The affected stream is pointed at a pipe with its other end closed instead:
+    /// reads return EOF and writes fail with `EPIPE`. Useful in minimal sandboxes without
+    /// device access. Defaults to `true` (fail startup).
+    #[cfg(unix)] pub fn strict_devnull(mut self, strict: bool) -> Self { self.strict_devnull = strict; self }
+    #[cfg(not(unix))] pub fn strict_devnull(self, _: bool) -> Self { self }
+
+    /// Keeps the original stdin, stdout and stderr (`Stdio::Keep`). Handy for debugging.
+    pub fn keep_all_stdio(mut self) -> Self {
+        self.stdin = Stdio::Keep;
+        self.stdout = Stdio::Keep;
+        self.stderr = Stdio::Keep;
+        self
+    }
+
+    /// Redirects stdin, stdout and stderr to `/dev/null` (`Stdio::Devnull`).
+    pub fn discard_all_stdio(mut self) -> Self {
+        self.stdin = Stdio::devnull();
+        self.stdout = Stdio::devnull();
+        self.stderr = Stdio::devnull();
+        self
+    }
+
+    /// (Windows, Unix with `RelaunchMode::Respawn`) Sets the exact arguments passed
+    /// to the relaunched daemon process.
+    ///
+    /// By default the child receives the launcher's own arguments (`env::args().skip(1)`).
+    /// Override this when those arguments contain a "daemonize now" subcommand that
+    /// must not be repeated, e.g. pass `["run"]` when the launcher was started with `["start"]`.
+    pub fn child_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.child_args = Some(args.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// (Windows, Unix with `RelaunchMode::Respawn`) Sets the name of the environment variable
+    /// that marks the relaunched process as the daemon. Defaults to `__DAEMONIZED_INTERNAL_FLAG`.
+    ///
+    /// Give each daemon its own name when one DaemonForge daemon launches another from
+    /// its launcher process, or when the default could already be set in the environment:
+    /// a process that finds the variable takes the daemon role. The daemon removes the
+    /// variable at startup, so programs it spawns never inherit it. The name must be
+    /// non-empty and contain neither `=` nor NUL, or `start()` fails with `DaemonError::ConfigError`.
+    pub fn internal_flag_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.internal_flag_name = Some(name.into());
+        self
+    }
+
+    /// (Windows) Passes extra handles (e.g. listening sockets) to the detached child.
+    ///
+    /// The handles are marked inheritable right before the child is spawned and their
+    /// values are listed in the `DAEMON_FORGE_HANDLES` variable; the child reads them with
+    /// `daemon_forge::inherited_handles()`. Any other process spawned concurrently by the
+    /// launcher would inherit them too.
+    #[cfg(windows)]
+    pub fn inherit_handles(mut self, handles: &[std::os::windows::io::RawHandle]) -> Self {
+        self.inherit_handles.extend_from_slice(handles);
+        self
+    }
+
     /// If `true`, clears all inherited environment variables for security.
+    ///
+    /// On Windows, `SystemRoot` is preserved because core system DLLs require it.
     pub fn clear_env(mut self, clear: bool) -> Self { self.clear_env = clear; self }
     
     /// Adds or overwrites an environment variable.
@@ -154,31 +624,262 @@ impl<SetupOutput> ForgeDaemon<SetupOutput> {
         self
     }
 
+    /// Requires the given variables to be present and non-empty in the daemon's final
+    /// environment (after `clear_env`, `env` and `inherit_env` are applied).
+    ///
+    /// `start()` fails with `DaemonError::EnvError` listing every missing variable.
+    pub fn require_env(mut self, keys: &[&str]) -> Self {
+        self.required_env.extend(keys.iter().map(|k| (*k).to_owned()));
+        self
+    }
+
     /// Inherits current environment variables into the configuration.
     /// 
     /// Useful when combined with `clear_env(true)` to selectively keep variables,
     /// or to ensure specific variables are captured before cleaning.
+    /// The environment is captured when this method is called; variables set later
+    /// are not included (see `inherit_env_deferred`).
     pub fn inherit_env(mut self) -> Self {
         for (k, v) in std::env::vars() {
-            self.env_vars.entry(k).or_insert(v);
+            self.env_vars.entry(k.clone()).or_insert_with(|| v.clone());
+            self.inherited_env.insert(k, v);
         }
         self
     }
 
+    /// Like `inherit_env`, but captures the environment when the daemon starts
+    /// (`start()`, `foreground_run()` or `into_parts()`) instead of now.
+    ///
+    /// For launchers that adjust their own environment between building and starting.
+    pub fn inherit_env_deferred(mut self) -> Self { self.inherit_env_deferred = true; self }
+
+    /// Writes the daemon's final environment to `path` as sorted `KEY=VALUE` lines once it
+    /// has been assembled, so operators can audit exactly what the daemon ran with.
+    ///
+    /// The file is replaced on every start. It may contain secrets: on Unix it is
+    /// created with mode `0600`. Relative paths are resolved against the launcher's
+    /// working directory.
+    pub fn dump_env_to<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.dump_env_to = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Decides which value wins when a key is set both by `env`/`env_opt` and by `inherit_env`.
+    ///
+    /// Applied when the daemon starts, so the result no longer depends on the order of
+    /// the builder calls. Defaults to `EnvPrecedence::ExplicitWins`.
+    pub fn env_precedence(mut self, precedence: EnvPrecedence) -> Self { self.env_precedence = precedence; self }
+
+    /// If `true`, creates the PID file's parent directory (recursively) when it is missing.
+    ///
+    /// The directory is created by the launching process before forking, while it
+    /// still has its original privileges. Useful for tmpfs-backed `/run` directories
+    /// that are empty after a reboot.
+    pub fn pid_dir_create(mut self, create: bool) -> Self { self.pid_dir_create = create; self }
+
+    /// If `true`, turns the single-instance lock into leader election (active/standby).
+    ///
+    /// Instead of failing with `TargetLocked`, further instances block on the lock
+    /// (`flock` on Unix, the named mutex on Windows) and only continue — running the
+    /// privileged action and returning from `start()` — once the current leader exits.
+    pub fn leader_election(mut self, enabled: bool) -> Self { self.leader_election = enabled; self }
+
+    /// Allows `build()` and `start()` to proceed without a lock identity (no `name`,
+    /// `pid_file` or `lock_file`), giving up the single-instance guarantee.
+    ///
+    /// Without it, such a configuration fails with `DaemonError::ConfigError` instead of
+    /// silently starting unlocked.
+    pub fn allow_no_lock(mut self, allow: bool) -> Self { self.allow_no_lock = allow; self }
+
     /// Validates configuration without starting the daemon.
-    /// Checks if the PID file directory exists.
+    /// Checks if the PID file directory exists (unless `pid_dir_create` is enabled)
+    /// and that the daemon has a lock identity (unless `allow_no_lock` is set).
     pub fn build(self) -> DaemonResult<Self> {
-        if let Some(pid) = &self.pid_file {
-            if pid.parent().map(|p| !p.exists()).unwrap_or(false) {
-                return Err(DaemonError::Io(std::io::Error::new(
-                    std::io::ErrorKind::NotFound, 
-                    "PID file directory does not exist"
-                )));
-            }
+        self.check_required_user()?;
+        self.check_lock_identity()?;
+        if let Some(pid) = &self.pid_file
+            && !self.pid_dir_create
+            && pid.parent().map(|p| !p.exists()).unwrap_or(false)
+        {
+            return Err(DaemonError::io("build", std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "PID file directory does not exist"
+            )));
         }
         Ok(self)
     }
 
+    /// Fails if nothing identifies the instance lock and `allow_no_lock` is not set.
+    fn check_lock_identity(&self) -> DaemonResult<()> {
+        #[cfg(unix)]
+        let has_lock_file = self.lock_file.is_some();
+        #[cfg(not(unix))]
+        let has_lock_file = false;
+
+        if self.allow_no_lock || self.name.is_some() || self.pid_file.is_some() || has_lock_file {
+            return Ok(());
+        }
+        Err(DaemonError::ConfigError(
+            "No lock identity: set a name, pid_file or lock_file, or allow_no_lock(true)".to_owned(),
+        ))
+    }
+
+    /// Fails if `require_user` is set but no user is configured.
+    fn check_required_user(&self) -> DaemonResult<()> {
+        #[cfg(unix)]
+        if self.require_user && self.user.is_none() {
+            return Err(DaemonError::ConfigError("A user is required but none was configured".to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Checks the whole configuration without starting the daemon and reports every problem found.
+    ///
+    /// Run it while still privileged and attached to the terminal (e.g. from a
+    /// `mydaemon check` subcommand). It verifies the working directory, the PID directory,
+    /// the chroot and runtime directories, user/group resolution, that redirected output
+    /// files are writable and that `require_env` variables are present.
+    /// An empty vector means no problems were detected.
+    ///
+    /// (Unix) `Stdio::file` logs are checked against the identity the daemon has after
+    /// dropping privileges (the `user`, and the `group` or the launcher's groups), since
+    /// `reopen_stdio_on_reload` reopens them as that user. The check compares mode bits
+    /// only: ACLs and read-only mounts are not taken into account.
+    pub fn validate_permissions(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+
+        if !self.directory.is_dir() {
+            problems.push(ConfigProblem::WorkingDirectoryMissing(self.directory.clone()));
+        }
+
+        if let Some(pid_path) = self.effective_pid_path()
+            && let Some(dir) = pid_path.parent()
+        {
+            let writable = if dir.exists() {
+                is_writable(dir)
+            } else {
+                self.pid_dir_create && nearest_existing_ancestor(dir).is_some_and(is_writable)
+            };
+            if !writable {
+                problems.push(ConfigProblem::PidDirectoryNotWritable(dir.to_path_buf()));
+            }
+        }
+
+        if !self.stdout.is_writable() {
+            problems.push(ConfigProblem::OutputNotWritable("stdout"));
+        }
+        if !self.stderr.is_writable() {
+            problems.push(ConfigProblem::OutputNotWritable("stderr"));
+        }
+
+        #[cfg(unix)]
+        {
+            if let Some(root) = &self.root
+                && !root.is_dir()
+            {
+                problems.push(ConfigProblem::ChrootMissing(root.clone()));
+            }
+
+            if let Some(dir) = &self.runtime_dir {
+                let dir = self.resolve_path(dir);
+                if !dir.is_dir() && !nearest_existing_ancestor(&dir).is_some_and(is_writable) {
+                    problems.push(ConfigProblem::RuntimeDirectoryNotCreatable(dir));
+                }
+            }
+
+            if let Some(user) = &self.user
+                && User::lookup(&user.0).is_err()
+            {
+                problems.push(ConfigProblem::UserNotFound(user.0.clone()));
+            }
+            for stdio in [&self.stdout, &self.stderr] {
+                if let Stdio::RedirectToPath(path) = stdio
+                    && !crate::sys::unix::is_writable_by(path, &self.user, &self.group)
+                {
+                    problems.push(ConfigProblem::LogFileNotWritable(path.clone()));
+                }
+            }
+            if let Some(group) = &self.group
+                && Group::lookup(&group.0).is_err()
+            {
+                problems.push(ConfigProblem::GroupNotFound(group.0.clone()));
+            }
+        }
+
+        problems.extend(self.missing_env().into_iter().map(|k| ConfigProblem::MissingEnv(k.to_owned())));
+        problems
+    }
+
+    /// Renders a systemd unit file reflecting this configuration.
+    ///
+    /// Typically printed by a `--dump-unit` style subcommand and redirected into
+    /// `/etc/systemd/system/<name>.service`. Pass `foreground` if the program runs the
+    /// daemon with `foreground_run()`: the unit then uses `Type=simple`. Otherwise it uses
+    /// `Type=forking` to match `start()`'s double-fork. systemd then finds the daemon
+    /// through `PIDFile`. Combine that with `launcher_exit_on_child_failure(true)`, so the
+    /// service only counts as started once the setup has succeeded.
+    ///
+    /// `ExecStart` is the current executable followed by `child_args`, if set.
+    /// `chroot` is left to the daemon itself and not translated to `RootDirectory`.
+    pub fn systemd_unit_template(&self, foreground: bool) -> String {
+        use std::fmt::Write;
+
+        let exe = std::env::current_exe().unwrap_or_default();
+        let description = self.name.clone().unwrap_or_else(|| {
+            exe.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+        });
+
+        let mut unit = String::new();
+        let _ = writeln!(unit, "[Unit]");
+        let _ = writeln!(unit, "Description={}", escape_unit_value(&description));
+        let _ = writeln!(unit);
+        let _ = writeln!(unit, "[Service]");
+        let _ = writeln!(unit, "Type={}", if foreground { "simple" } else { "forking" });
+
+        // ExecStart also expands `$VAR`
+        let mut exec_start = quote_unit_word(&exe.to_string_lossy()).replace('$', "$$");
+        for arg in self.child_args.iter().flatten() {
+            exec_start.push(' ');
+            exec_start.push_str(&quote_unit_word(arg).replace('$', "$$"));
+        }
+        let _ = writeln!(unit, "ExecStart={}", exec_start);
+
+        let directory = if self.directory.is_relative() {
+            std::env::current_dir().map(|cwd| cwd.join(&self.directory)).unwrap_or_else(|_| self.directory.clone())
+        } else {
+            self.directory.clone()
+        };
+        let _ = writeln!(unit, "WorkingDirectory={}", escape_unit_value(&directory.to_string_lossy()));
+
+        #[cfg(unix)]
+        {
+            if let Some(user) = &self.user {
+                let _ = writeln!(unit, "User={}", user.0);
+            }
+            if let Some(group) = &self.group {
+                let _ = writeln!(unit, "Group={}", group.0);
+            }
+            if let Some(mask) = self.umask {
+                let _ = writeln!(unit, "UMask={:04o}", mask);
+            }
+        }
+
+        let mut env: Vec<_> = self.env_vars.iter().collect();
+        env.sort();
+        for (k, v) in env {
+            let _ = writeln!(unit, "Environment={}", quote_unit_word(&format!("{}={}", k, v)));
+        }
+
+        if let Some(pid_path) = self.effective_pid_path() {
+            let _ = writeln!(unit, "PIDFile={}", escape_unit_value(&pid_path.to_string_lossy()));
+        }
+
+        let _ = writeln!(unit);
+        let _ = writeln!(unit, "[Install]");
+        let _ = writeln!(unit, "WantedBy=multi-user.target");
+        unit
+    }
+
     /// Executes an action before dropping privileges (Unix) or before entering the main loop.
     /// 
     /// The action MUST return a `DaemonResult`. If it returns `Err`, the daemon will abort startup.
@@ -187,6 +888,161 @@ impl<SetupOutput> ForgeDaemon<SetupOutput> {
     where 
         F: FnOnce() -> DaemonResult<N> + 'static 
     {
+        self.with_action(Box::new(action))
+    }
+
+    /// Starts a watchdog thread right before the privileged action that expects
+    /// `daemon_forge::heartbeat()` at least once every `timeout`.
+    ///
+    /// When the heartbeats stop (e.g. the main loop deadlocked), the watchdog logs to
+    /// stderr and applies `action`: abort the process, or call a handler.
+    /// Independent of systemd's `WatchdogSec`, for hosts without an external supervisor.
+    pub fn hang_watchdog(mut self, timeout: std::time::Duration, action: WatchdogAction) -> Self {
+        self.hang_watchdog = Some((timeout, action));
+        self
+    }
+
+    /// Runs a hook once the daemon is fully set up: PID file written, privileged action
+    /// completed and privileges dropped, right before `start()` returns.
+    ///
+    /// Runs as the final, unprivileged user, which makes it the place to report readiness
+    /// or register with service discovery. If it returns `Err`, `start()` returns that error.
+    /// In `foreground_run`, it runs before the closure.
+    pub fn on_ready<F>(mut self, hook: F) -> Self
+    where
+        F: FnOnce() -> DaemonResult<()> + 'static,
+    {
+        self.on_ready = Some(Box::new(hook));
+        self
+    }
+
+    /// Runs a hook in the daemon when its setup (environment, directories, chroot, PID
+    /// file, ...), the privileged action or `on_ready` fails, before the error is returned,
+    /// e.g. to remove a half-created runtime directory or undo what the action registered.
+    ///
+    /// On Unix it runs in the daemon process while the instance lock is still held; on
+    /// Windows, in the detached child. In `foreground_run` it does not cover the closure.
+    /// An `Err` from the hook is written to stderr; the original error is returned.
+    pub fn on_setup_failure<F>(mut self, hook: F) -> Self
+    where
+        F: FnOnce() -> DaemonResult<()> + 'static,
+    {
+        self.on_setup_failure = Some(Box::new(hook));
+        self
+    }
+
+    /// (Unix) Calls `hook` on every `SIGHUP`, typically to re-read the configuration in place.
+    ///
+    /// The hook runs on a dedicated signal-handling thread started right before the
+    /// privileged action, never in signal context. Errors are written to stderr and the
+    /// daemon keeps running with its previous configuration; a failed reload never stops
+    /// the process. After a successful reload, `daemon_forge::take_reload_request()`
+    /// returns `true` once.
+    #[cfg(unix)]
+    pub fn on_reload<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut() -> DaemonResult<()> + Send + 'static,
+    {
+        self.on_reload = Some(Box::new(hook));
+        self
+    }
+    #[cfg(not(unix))]
+    pub fn on_reload<F>(self, _: F) -> Self
+    where
+        F: FnMut() -> DaemonResult<()> + Send + 'static,
+    {
+        self
+    }
+
+    /// (Unix) Handles `SIGTERM` and `SIGINT` as a graceful shutdown request, with `hook`
+    /// as the cleanup to run.
+    ///
+    /// The signal handler only sets a flag: `hook` never runs inside it, since almost
+    /// nothing (allocating, locking, most IO) is async-signal-safe. The main loop checks
+    /// `daemon_forge::is_shutdown_requested()` or polls a `ShutdownGuard`, which runs
+    /// `hook` on its own thread. The signals no longer terminate the process: a daemon
+    /// that never checks the flag keeps running until `SIGKILL`.
+    #[cfg(unix)]
+    pub fn on_shutdown<F>(mut self, hook: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.on_shutdown = Some(Box::new(move || {
+            hook();
+            Ok(())
+        }));
+        self
+    }
+    #[cfg(not(unix))]
+    pub fn on_shutdown<F>(self, _: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self
+    }
+
+    /// (Unix) Like `on_shutdown`, for cleanup that can fail (e.g. a final flush to a database).
+    /// Replaces any `on_shutdown` hook.
+    ///
+    /// If `hook` returns `Err`, the error is written to stderr as a `[DaemonForge Critical]`
+    /// line (see `last_error_from_log`) and the process exits right away with the error's
+    /// `exit_code()`, so supervisors notice the unclean shutdown.
+    #[cfg(unix)]
+    pub fn on_shutdown_fallible<F>(mut self, hook: F) -> Self
+    where
+        F: FnOnce() -> DaemonResult<()> + Send + 'static,
+    {
+        self.on_shutdown = Some(Box::new(hook));
+        self
+    }
+    #[cfg(not(unix))]
+    pub fn on_shutdown_fallible<F>(self, _: F) -> Self
+    where
+        F: FnOnce() -> DaemonResult<()> + Send + 'static,
+    {
+        self
+    }
+
+    /// (Unix) On every `SIGHUP`, reopens stdout/stderr configured with `Stdio::file`,
+    /// after the `on_reload` hook (if any) succeeded.
+    ///
+    /// The hook may point them at new locations with `daemon_forge::update_stdio_paths`;
+    /// otherwise the same paths are reopened, which also suits `logrotate`. The new file
+    /// is `dup2`'d over the descriptor, so the swap is atomic and in-flight writes land in
+    /// the old file. Paths are resolved in the daemon's current root (inside the `chroot`).
+    #[cfg(unix)] pub fn reopen_stdio_on_reload(mut self, reopen: bool) -> Self { self.reopen_stdio_on_reload = reopen; self }
+    #[cfg(not(unix))] pub fn reopen_stdio_on_reload(self, _: bool) -> Self { self }
+
+    /// Runs a hook in the original, user-visible process before any forking (Unix)
+    /// or before the detached child is spawned (Windows).
+    ///
+    /// Use it for work that must happen while still attached to the terminal, such as
+    /// printing to the console or acquiring a resource that must survive the fork.
+    /// If it returns `Err`, startup is aborted before anything is forked.
+    pub fn before_fork<F>(mut self, hook: F) -> Self
+    where
+        F: FnOnce() -> DaemonResult<()> + 'static,
+    {
+        self.before_fork = Some(Box::new(hook));
+        self
+    }
+
+    /// Composes another privileged action after the current one.
+    ///
+    /// `action` receives the previous action's output and runs only if it succeeded,
+    /// so the first error still aborts startup. Lets libraries contribute setup steps
+    /// without clobbering the application's own action.
+    pub fn then_privileged_action<N, F>(mut self, action: F) -> ForgeDaemon<N>
+    where
+        F: FnOnce(SetupOutput) -> DaemonResult<N> + 'static,
+        SetupOutput: 'static,
+    {
+        let previous = self.privileged_action.take().unwrap();
+        self.with_action(Box::new(move || action(previous()?)))
+    }
+
+    /// Moves the configuration into a builder with a different privileged action.
+    fn with_action<N>(self, action: Box<dyn FnOnce() -> DaemonResult<N>>) -> ForgeDaemon<N> {
         ForgeDaemon {
             name: self.name,
             directory: self.directory,
@@ -194,6 +1050,24 @@ impl<SetupOutput> ForgeDaemon<SetupOutput> {
             stdin: self.stdin,
             stdout: self.stdout,
             stderr: self.stderr,
+            pid_dir_create: self.pid_dir_create,
+            required_env: self.required_env,
+            child_args: self.child_args,
+            before_fork: self.before_fork,
+            leader_election: self.leader_election,
+            launcher_exit_on_failure: self.launcher_exit_on_failure,
+            env_precedence: self.env_precedence,
+            inherited_env: self.inherited_env,
+            exe_sha256: self.exe_sha256,
+            on_ready: self.on_ready,
+            hang_watchdog: self.hang_watchdog,
+            inherit_env_deferred: self.inherit_env_deferred,
+            dump_env_to: self.dump_env_to,
+            additional_pid_files: self.additional_pid_files,
+            create_log_dirs: self.create_log_dirs,
+            allow_no_lock: self.allow_no_lock,
+            on_setup_failure: self.on_setup_failure,
+            internal_flag_name: self.internal_flag_name,
             clear_env: self.clear_env,
             env_vars: self.env_vars,
             #[cfg(unix)] user: self.user,
@@ -201,7 +1075,145 @@ impl<SetupOutput> ForgeDaemon<SetupOutput> {
             #[cfg(unix)] umask: self.umask,
             #[cfg(unix)] root: self.root,
             #[cfg(unix)] chown_pid: self.chown_pid,
-            privileged_action: Some(Box::new(action)),
+            #[cfg(unix)] launcher_linger: self.launcher_linger,
+            #[cfg(unix)] verify_pid: self.verify_pid,
+            #[cfg(unix)] pid_dir_mode: self.pid_dir_mode,
+            #[cfg(unix)] chroot_chdir: self.chroot_chdir,
+            #[cfg(unix)] runtime_dir: self.runtime_dir,
+            #[cfg(unix)] runtime_dir_setgid: self.runtime_dir_setgid,
+            #[cfg(unix)] pid_fallback_dir: self.pid_fallback_dir,
+            #[cfg(unix)] strict_devnull: self.strict_devnull,
+            #[cfg(unix)] core_dump_dir: self.core_dump_dir,
+            #[cfg(all(unix, feature = "pty"))] pty: self.pty,
+            #[cfg(unix)] detect_pid_tampering: self.detect_pid_tampering,
+            #[cfg(unix)] block_signals_during_setup: self.block_signals_during_setup,
+            #[cfg(unix)] logs_nofollow: self.logs_nofollow,
+            #[cfg(unix)] profile_startup: self.profile_startup,
+            #[cfg(unix)] relaunch_mode: self.relaunch_mode,
+            #[cfg(target_os = "linux")] oom_score_adj: self.oom_score_adj,
+            #[cfg(unix)] on_reload: self.on_reload,
+            #[cfg(unix)] die_with_parent: self.die_with_parent,
+            #[cfg(windows)] inherit_handles: self.inherit_handles,
+            #[cfg(unix)] tmp_dir: self.tmp_dir,
+            #[cfg(unix)] reopen_stdio_on_reload: self.reopen_stdio_on_reload,
+            #[cfg(unix)] abort_if_root: self.abort_if_root,
+            #[cfg(unix)] require_user: self.require_user,
+            #[cfg(unix)] lock_file: self.lock_file,
+            #[cfg(unix)] container_aware: self.container_aware,
+            #[cfg(unix)] panic_to_syslog: self.panic_to_syslog,
+            #[cfg(unix)] capture_backtrace: self.capture_backtrace,
+            #[cfg(unix)] sigpipe: self.sigpipe,
+            #[cfg(unix)] pid_file_mode: self.pid_file_mode,
+            #[cfg(unix)] log_dir_mode: self.log_dir_mode,
+            #[cfg(unix)] debug_signal: self.debug_signal,
+            #[cfg(unix)] privilege_regainable: self.privilege_regainable,
+            #[cfg(unix)] pid_sync_barrier: self.pid_sync_barrier,
+            #[cfg(unix)] reexec_on_upgrade: self.reexec_on_upgrade,
+            #[cfg(unix)] lock_style: self.lock_style,
+            #[cfg(target_os = "linux")] log_reserve_bytes: self.log_reserve_bytes,
+            #[cfg(unix)] set_user_env: self.set_user_env,
+            #[cfg(target_os = "linux")] assert_clean_fds: self.assert_clean_fds,
+            #[cfg(unix)] require_setsid: self.require_setsid,
+            #[cfg(unix)] pid_heartbeat: self.pid_heartbeat,
+            #[cfg(unix)] signal_pipe: self.signal_pipe,
+            #[cfg(target_os = "linux")] ionice: self.ionice,
+            #[cfg(unix)] socket_umask: self.socket_umask,
+            #[cfg(unix)] on_shutdown: self.on_shutdown,
+            #[cfg(unix)] close_fds: self.close_fds,
+            #[cfg(unix)] preserved_fds: self.preserved_fds,
+            privileged_action: Some(action),
+        }
+    }
+
+    /// Copies the configuration into a new builder, e.g. to launch several similar daemons
+    /// (a worker pool with different names and PID files) from one template.
+    ///
+    /// What can't be cloned is reset to its default: the privileged action and the
+    /// `before_fork`, `on_ready`, `on_setup_failure`, `on_reload` and `on_shutdown` hooks, a `hang_watchdog` calling a handler,
+    /// and stdio given as an open `File` or `TcpStream` (which becomes `Stdio::Devnull`).
+    pub fn clone_config(&self) -> ForgeDaemon<()> {
+        ForgeDaemon {
+            name: self.name.clone(),
+            directory: self.directory.clone(),
+            pid_file: self.pid_file.clone(),
+            stdin: self.stdin.clone_config(),
+            stdout: self.stdout.clone_config(),
+            stderr: self.stderr.clone_config(),
+            pid_dir_create: self.pid_dir_create,
+            required_env: self.required_env.clone(),
+            child_args: self.child_args.clone(),
+            before_fork: None,
+            leader_election: self.leader_election,
+            launcher_exit_on_failure: self.launcher_exit_on_failure,
+            env_precedence: self.env_precedence,
+            inherited_env: self.inherited_env.clone(),
+            exe_sha256: self.exe_sha256.clone(),
+            on_ready: None,
+            hang_watchdog: match &self.hang_watchdog {
+                Some((timeout, WatchdogAction::Abort)) => Some((*timeout, WatchdogAction::Abort)),
+                _ => None,
+            },
+            inherit_env_deferred: self.inherit_env_deferred,
+            dump_env_to: self.dump_env_to.clone(),
+            additional_pid_files: self.additional_pid_files.clone(),
+            create_log_dirs: self.create_log_dirs,
+            allow_no_lock: self.allow_no_lock,
+            on_setup_failure: None,
+            internal_flag_name: self.internal_flag_name.clone(),
+            clear_env: self.clear_env,
+            env_vars: self.env_vars.clone(),
+            #[cfg(unix)] user: self.user.clone(),
+            #[cfg(unix)] group: self.group.clone(),
+            #[cfg(unix)] umask: self.umask,
+            #[cfg(unix)] root: self.root.clone(),
+            #[cfg(unix)] chown_pid: self.chown_pid,
+            #[cfg(unix)] launcher_linger: self.launcher_linger,
+            #[cfg(unix)] verify_pid: self.verify_pid,
+            #[cfg(unix)] pid_dir_mode: self.pid_dir_mode,
+            #[cfg(unix)] chroot_chdir: self.chroot_chdir,
+            #[cfg(unix)] runtime_dir: self.runtime_dir.clone(),
+            #[cfg(unix)] runtime_dir_setgid: self.runtime_dir_setgid,
+            #[cfg(unix)] pid_fallback_dir: self.pid_fallback_dir.clone(),
+            #[cfg(unix)] strict_devnull: self.strict_devnull,
+            #[cfg(unix)] core_dump_dir: self.core_dump_dir.clone(),
+            #[cfg(all(unix, feature = "pty"))] pty: self.pty,
+            #[cfg(unix)] detect_pid_tampering: self.detect_pid_tampering,
+            #[cfg(unix)] block_signals_during_setup: self.block_signals_during_setup,
+            #[cfg(unix)] logs_nofollow: self.logs_nofollow,
+            #[cfg(unix)] profile_startup: self.profile_startup,
+            #[cfg(unix)] relaunch_mode: self.relaunch_mode,
+            #[cfg(target_os = "linux")] oom_score_adj: self.oom_score_adj,
+            #[cfg(unix)] on_reload: None,
+            #[cfg(unix)] die_with_parent: self.die_with_parent,
+            #[cfg(windows)] inherit_handles: self.inherit_handles.clone(),
+            #[cfg(unix)] tmp_dir: self.tmp_dir.clone(),
+            #[cfg(unix)] reopen_stdio_on_reload: self.reopen_stdio_on_reload,
+            #[cfg(unix)] abort_if_root: self.abort_if_root,
+            #[cfg(unix)] require_user: self.require_user,
+            #[cfg(unix)] lock_file: self.lock_file.clone(),
+            #[cfg(unix)] container_aware: self.container_aware,
+            #[cfg(unix)] panic_to_syslog: self.panic_to_syslog,
+            #[cfg(unix)] capture_backtrace: self.capture_backtrace,
+            #[cfg(unix)] sigpipe: self.sigpipe,
+            #[cfg(unix)] pid_file_mode: self.pid_file_mode,
+            #[cfg(unix)] log_dir_mode: self.log_dir_mode,
+            #[cfg(unix)] debug_signal: self.debug_signal,
+            #[cfg(unix)] privilege_regainable: self.privilege_regainable,
+            #[cfg(unix)] pid_sync_barrier: self.pid_sync_barrier,
+            #[cfg(unix)] reexec_on_upgrade: self.reexec_on_upgrade,
+            #[cfg(unix)] lock_style: self.lock_style,
+            #[cfg(target_os = "linux")] log_reserve_bytes: self.log_reserve_bytes,
+            #[cfg(unix)] set_user_env: self.set_user_env,
+            #[cfg(target_os = "linux")] assert_clean_fds: self.assert_clean_fds,
+            #[cfg(unix)] require_setsid: self.require_setsid,
+            #[cfg(unix)] pid_heartbeat: self.pid_heartbeat,
+            #[cfg(unix)] signal_pipe: self.signal_pipe.clone(),
+            #[cfg(target_os = "linux")] ionice: self.ionice,
+            #[cfg(unix)] socket_umask: self.socket_umask,
+            #[cfg(unix)] on_shutdown: None,
+            #[cfg(unix)] close_fds: self.close_fds,
+            #[cfg(unix)] preserved_fds: self.preserved_fds.clone(),
+            privileged_action: Some(Box::new(|| Ok(()))),
         }
     }
 
@@ -219,32 +1231,1140 @@ impl<SetupOutput> ForgeDaemon<SetupOutput> {
     #[cfg(unix)] pub fn umask(mut self, mask: u32) -> Self { self.umask = Some(mask); self }
     #[cfg(not(unix))] pub fn umask(self, _: u32) -> Self { self }
 
+    /// (Unix) Sets the umask from a symbolic spec such as `"u=rwx,g=rx,o="`.
+    ///
+    /// The spec lists the permissions to *keep*, like `umask -S`: `"u=rwx,g=rx,o="` is `0o027`.
+    /// Clauses may use `=`, `+` or `-` (the latter two relative to the current umask), and
+    /// `a` or an empty target means all classes. Plain octal strings (`"077"`) are accepted too.
+    /// Returns `ConfigError` on malformed input.
+    #[cfg(unix)]
+    pub fn umask_symbolic(mut self, spec: &str) -> DaemonResult<Self> {
+        let current = self.umask.unwrap_or(0o022);
+        let mask = parse_symbolic_umask(spec, current).ok_or_else(|| {
+            DaemonError::ConfigError(format!("Invalid umask specification '{}'", spec))
+        })?;
+        self.umask = Some(mask);
+        Ok(self)
+    }
+    #[cfg(not(unix))] pub fn umask_symbolic(self, _: &str) -> DaemonResult<Self> { Ok(self) }
+
+    /// (Unix) Umask in effect only while the privileged action runs, for creating Unix
+    /// domain sockets clients can connect to; typically `0o117` (`srw-rw----`).
+    ///
+    /// With the daemon umask (`0o027` by default) a socket is created `srw-r-----`, and
+    /// group members get `EACCES` on `connect`. The daemon umask is restored once the
+    /// action returns. Everything the action creates gets this mask, including
+    /// directories (`0o117` leaves them untraversable), and it applies process-wide.
+    ///
+    /// Not set by default: a `0o117` default would make every directory an existing
+    /// action creates `drw-rw----`, which even its owner can't enter.
+    #[cfg(unix)] pub fn socket_umask(mut self, mask: u32) -> Self { self.socket_umask = Some(mask); self }
+    #[cfg(not(unix))] pub fn socket_umask(self, _: u32) -> Self { self }
+
     /// (Unix) Sets a chroot directory for the daemon.
     #[cfg(unix)] pub fn chroot<P: Into<PathBuf>>(mut self, path: P) -> Self { self.root = Some(path.into()); self }
     #[cfg(not(unix))] pub fn chroot<P>(self, _: P) -> Self { self }
 
+    /// (Unix) If false, `chroot` is applied without the follow-up `chdir("/")`.
+    ///
+    /// The working directory is then left outside the jail and must be changed by the
+    /// privileged action itself; otherwise the process can escape the chroot through it.
+    /// Defaults to `true`.
+    #[cfg(unix)] pub fn chroot_chdir(mut self, chdir: bool) -> Self { self.chroot_chdir = chdir; self }
+    #[cfg(not(unix))] pub fn chroot_chdir(self, _: bool) -> Self { self }
+
+    /// (Unix) Sets a runtime directory (sockets, state files) owned by the daemon.
+    ///
+    /// It is created if missing with mode `0o750` and chowned to the target user/group
+    /// before privileges are dropped; an existing directory keeps its owner and mode.
+    /// The path is resolved inside the `chroot`, if any.
+    #[cfg(unix)] pub fn runtime_directory<P: Into<PathBuf>>(mut self, path: P) -> Self { self.runtime_dir = Some(path.into()); self }
+    #[cfg(not(unix))] pub fn runtime_directory<P>(self, _: P) -> Self { self }
+
+    /// (Unix) Points `TMPDIR`, `TMP` and `TEMP` at `path` before the privileged action runs.
+    ///
+    /// The directory is created if missing with mode `0o700` and chowned to the target
+    /// user/group, so libraries writing temp files keep working after the privilege drop
+    /// or inside a `chroot` (the path is resolved inside the jail). An existing directory
+    /// is left as is; setup fails with `ConfigError` if the target user can't write to it.
+    #[cfg(unix)] pub fn redirect_tmpdir<P: Into<PathBuf>>(mut self, path: P) -> Self { self.tmp_dir = Some(path.into()); self }
+    #[cfg(not(unix))] pub fn redirect_tmpdir<P>(self, _: P) -> Self { self }
+
+    /// (Unix) If true, sets the setgid bit on the runtime directory (mode `02750`)
+    /// when it creates it, so files created inside inherit its group, e.g. for a
+    /// monitoring user.
+    #[cfg(unix)] pub fn runtime_dir_setgid(mut self, setgid: bool) -> Self { self.runtime_dir_setgid = setgid; self }
+    #[cfg(not(unix))] pub fn runtime_dir_setgid(self, _: bool) -> Self { self }
+
+    /// (Unix) Enables core dumps for the daemon, written to `dir`.
+    ///
+    /// Raises `RLIMIT_CORE` to unlimited (or to the hard limit when not root), marks the
+    /// process dumpable again after `setuid` on Linux (which clears the flag), and installs
+    /// a handler for crash signals that changes into `dir` before the core is produced.
+    /// `dir` must be writable by the target user and is resolved inside the `chroot`, if any.
+    #[cfg(unix)] pub fn enable_core_dumps<P: Into<PathBuf>>(mut self, dir: P) -> Self { self.core_dump_dir = Some(dir.into()); self }
+    #[cfg(not(unix))] pub fn enable_core_dumps<P>(self, _: P) -> Self { self }
+
+    /// (Unix, feature `pty`) Runs the daemon with a pseudo-terminal as its stdin/stdout/stderr.
+    ///
+    /// For diagnosing tty-dependent behavior. The launcher keeps the master side and relays
+    /// it to its own terminal (and its stdin back to the daemon) until the daemon closes the
+    /// slave side. The pty is not made the controlling terminal, so the daemon stays detached.
+    /// Takes precedence over the configured `stdin`/`stdout`/`stderr` and `launcher_linger`.
+    /// Ignored in systemd and foreground modes.
+    #[cfg(all(unix, feature = "pty"))] pub fn pty(mut self, enable: bool) -> Self { self.pty = enable; self }
+    #[cfg(all(not(unix), feature = "pty"))] pub fn pty(self, _: bool) -> Self { self }
+
+    /// (Unix) If true, startup fails with `DaemonError::PrivilegeError` when the process
+    /// is still running as root (effective UID `0`) after the privilege drop.
+    ///
+    /// Catches a forgotten `.user()` or a drop that silently did nothing. Checked once
+    /// the privileged action has returned and privileges were dropped, before `on_ready`.
+    #[cfg(unix)] pub fn abort_if_root(mut self, abort: bool) -> Self { self.abort_if_root = abort; self }
+    #[cfg(not(unix))] pub fn abort_if_root(self, _: bool) -> Self { self }
+
+    /// (Unix) If true, the privilege drop keeps root as the saved set-user-ID, so the
+    /// daemon can temporarily switch back with `regain_privileges()` (e.g. to re-bind a
+    /// privileged port) and return with `drop_privileges()`.
+    ///
+    /// **Less secure than the default permanent drop:** any code execution bug in the
+    /// daemon can regain root the same way. Only the user ID is regainable; the group
+    /// drop stays permanent. Uses `setresuid` where available, `seteuid` elsewhere.
+    #[cfg(unix)] pub fn privilege_regainable(mut self, enable: bool) -> Self { self.privilege_regainable = enable; self }
+    #[cfg(not(unix))] pub fn privilege_regainable(self, _: bool) -> Self { self }
+
+    /// (Unix) If true, sets `HOME`, `SHELL`, `USER` and `LOGNAME` from the target user's
+    /// passwd entry when dropping to the configured `user`, as `su -` does.
+    ///
+    /// Otherwise the daemon keeps the launcher's values (typically root's), and libraries
+    /// looking up config or cache directories through `$HOME` use the wrong one. The
+    /// shell is taken as recorded (`/usr/sbin/nologin` for most system users), or
+    /// `/bin/sh` when the entry leaves it empty.
+    #[cfg(unix)] pub fn set_user_env(mut self, enable: bool) -> Self { self.set_user_env = enable; self }
+    #[cfg(not(unix))] pub fn set_user_env(self, _: bool) -> Self { self }
+
+    /// (Unix) If false, a `setsid` failing with `EPERM` (the process already leads a
+    /// process group, as in some init setups) is tolerated: the daemon keeps its current
+    /// session and a warning is written to its redirected stderr. Defaults to `true`,
+    /// failing startup with `DaemonError::SyscallError`.
+    ///
+    /// A daemon that stays in the launcher's session can still receive that session's
+    /// `SIGHUP` when its terminal closes.
+    #[cfg(unix)] pub fn require_setsid(mut self, require: bool) -> Self { self.require_setsid = require; self }
+    #[cfg(not(unix))] pub fn require_setsid(self, _: bool) -> Self { self }
+
+    /// (Unix) Makes `build()`, `start()` and `foreground_run()` fail with
+    /// `DaemonError::ConfigError` when no `.user()` is configured.
+    #[cfg(unix)] pub fn require_user(mut self) -> Self { self.require_user = true; self }
+    #[cfg(not(unix))] pub fn require_user(self) -> Self { self }
+
+    /// (Unix) If true, the daemon replaces the default panic hook with one that writes the
+    /// panic and a full backtrace to stderr (usually the log file), then syncs it to disk.
+    ///
+    /// The backtrace is captured regardless of `RUST_BACKTRACE`, and the sync lets the
+    /// record survive an immediate abort (`panic = "abort"`, `hang_watchdog`).
+    #[cfg(unix)] pub fn capture_backtrace(mut self, enable: bool) -> Self { self.capture_backtrace = enable; self }
+    #[cfg(not(unix))] pub fn capture_backtrace(self, _: bool) -> Self { self }
+
+    /// (Unix) If true, panics are also sent to syslog (`LOG_DAEMON`, `LOG_CRIT`) with their
+    /// message, location and, when `RUST_BACKTRACE` enables it, the backtrace.
+    ///
+    /// Independent of stderr redirection: crashes reach the central log even when the
+    /// local log file is what's broken (disk full). The previous panic hook still runs.
+    /// Uses the daemon `name` as the syslog identifier.
+    #[cfg(unix)] pub fn panic_to_syslog(mut self, enable: bool) -> Self { self.panic_to_syslog = enable; self }
+    #[cfg(not(unix))] pub fn panic_to_syslog(self, _: bool) -> Self { self }
+
     /// (Unix) If true, changes ownership of the PID file to the target user/group.
     #[cfg(unix)] pub fn chown_pid_file(mut self, chown: bool) -> Self { self.chown_pid = chown; self }
     #[cfg(not(unix))] pub fn chown_pid_file(self, _: bool) -> Self { self }
 
+    /// (Unix) Sets the directory for the PID file derived from `name` when no `pid_file` is set.
+    ///
+    /// Defaults to `std::env::temp_dir()`. Hardened systems often mount `/tmp` per-user or
+    /// `noexec`, making it unsuitable as a shared lock location; `/run` is a common choice.
+    #[cfg(unix)] pub fn pid_fallback_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self { self.pid_fallback_dir = Some(dir.into()); self }
+    #[cfg(not(unix))] pub fn pid_fallback_dir<P>(self, _: P) -> Self { self }
+
+    /// (Unix) Sets the mode used when `pid_dir_create` creates the PID directory. Defaults to `0o755`.
+    #[cfg(unix)] pub fn pid_dir_mode(mut self, mode: u32) -> Self { self.pid_dir_mode = mode; self }
+    #[cfg(not(unix))] pub fn pid_dir_mode(self, _: u32) -> Self { self }
+
+    /// (Unix) If true, the PID file (and any `additional_pid_files`) is `fsync`ed after being
+    /// written, followed by its parent directory, so the new directory entry is durable
+    /// and visible before the daemon proceeds.
+    ///
+    /// For network and overlay filesystems where other nodes may otherwise read a stale
+    /// "not running" state. Costs one or two `fsync` calls at startup.
+    #[cfg(unix)] pub fn pid_sync_barrier(mut self, enable: bool) -> Self { self.pid_sync_barrier = enable; self }
+    #[cfg(not(unix))] pub fn pid_sync_barrier(self, _: bool) -> Self { self }
+
+    /// (Unix) Sets the mode of the PID file (and `lock_file`, `additional_pid_files`).
+    /// Defaults to `0o644`.
+    ///
+    /// Applied with `fchmod` once the file is open, so a restrictive `umask` can't leave
+    /// the daemon's own PID file unreadable.
+    #[cfg(unix)] pub fn pid_file_mode(mut self, mode: u32) -> Self { self.pid_file_mode = mode; self }
+    #[cfg(not(unix))] pub fn pid_file_mode(self, _: u32) -> Self { self }
+
+    /// (Linux) Sets the daemon's OOM killer score adjustment (`/proc/self/oom_score_adj`).
+    ///
+    /// The value is clamped to `-1000..=1000` and written after the final fork, before
+    /// privileges are dropped: values below `0` require privileges. Startup fails with
+    /// `DaemonError::PrivilegeError` if the kernel denies the write.
+    #[cfg(target_os = "linux")] pub fn oom_score_adj(mut self, adj: i32) -> Self { self.oom_score_adj = Some(adj.clamp(-1000, 1000)); self }
+    #[cfg(not(target_os = "linux"))] pub fn oom_score_adj(self, _: i32) -> Self { self }
+
+    /// (Linux) Sets the daemon's IO scheduling class and priority (`ioprio_set`), e.g.
+    /// `IoClass::Idle` for a disk-bound job that must not slow down interactive work.
+    ///
+    /// Applied after the final fork, before privileges are dropped; threads the daemon
+    /// starts later inherit it. `IoClass::Realtime` requires privileges: startup fails
+    /// with `DaemonError::PrivilegeError` if the kernel refuses.
+    #[cfg(target_os = "linux")] pub fn ionice(mut self, class: IoClass) -> Self { self.ionice = Some(class); self }
+    #[cfg(not(target_os = "linux"))] pub fn ionice(self, _: IoClass) -> Self { self }
+
+    /// (Linux) Preallocates `bytes` of disk space past the end of the stdout/stderr log
+    /// files (`fallocate` with `FALLOC_FL_KEEP_SIZE`), so a crash log can still be written
+    /// once the disk is full. Defaults to `0` (no reservation).
+    ///
+    /// Applied to stdout and stderr when they refer to regular files, and again when
+    /// `reopen_stdio_on_reload` reopens them. The file size is unchanged: appends consume
+    /// the reservation. Startup fails if the space can't be reserved (full disk, or a
+    /// filesystem without `fallocate`).
+    #[cfg(target_os = "linux")] pub fn log_reserve_bytes(mut self, bytes: u64) -> Self { self.log_reserve_bytes = bytes; self }
+    #[cfg(not(target_os = "linux"))] pub fn log_reserve_bytes(self, _: u64) -> Self { self }
+
+    /// (Linux) If true, fails startup with `DaemonError::PrivilegeError` when the daemon
+    /// has descriptors open besides stdin, stdout and stderr, listing them with their
+    /// `/proc/self/fd` targets.
+    ///
+    /// Checked once stdio is set up (after the second fork in background mode), before
+    /// the environment is touched. Descriptors DaemonForge passes on itself are expected:
+    /// `READY_FD`, the launcher status pipe, the lock inherited by `reexec_on_upgrade`
+    /// and systemd's socket-activation range (`LISTEN_FDS`, or the descriptors taken with
+    /// `listen_fds_with_names()`), as are files and streams passed as `Stdio`.
+    /// Verifies only; nothing is closed (see `close_fds`).
+    #[cfg(target_os = "linux")] pub fn assert_clean_fds(mut self, enable: bool) -> Self { self.assert_clean_fds = enable; self }
+    #[cfg(not(target_os = "linux"))] pub fn assert_clean_fds(self, _: bool) -> Self { self }
+
+    /// (Unix) If true, closes every descriptor above stderr the daemon inherited, so it
+    /// does not hold locks or sockets of the launching shell. Disabled by default.
+    ///
+    /// Runs at the same point as `assert_clean_fds`, and spares the same descriptors.
+    /// Open descriptors are listed from `/proc/self/fd`; without it (no procfs, or
+    /// non-Linux), every number up to `sysconf(_SC_OPEN_MAX)` is closed.
+    #[cfg(unix)] pub fn close_fds(mut self, enable: bool) -> Self { self.close_fds = enable; self }
+    #[cfg(not(unix))] pub fn close_fds(self, _: bool) -> Self { self }
+
+    /// (Unix) Keeps `fd` open in the daemon, e.g. a listening socket bound by the launcher.
+    /// Can be called several times.
+    ///
+    /// Preserved descriptors are never closed by `close_fds`, nor reported by
+    /// `assert_clean_fds`. Their `FD_CLOEXEC` flag is cleared before forking, so they also
+    /// survive `RelaunchMode::Respawn` and `reexec_on_upgrade`, under the same number:
+    ///
+    /// ```no_run
+    /// use std::net::TcpListener;
+    /// use std::os::unix::io::{AsRawFd, FromRawFd};
+    ///
+    /// let listener = TcpListener::bind("127.0.0.1:8080")?;
+    /// let fd = listener.as_raw_fd();
+    /// daemon_forge::ForgeDaemon::new()
+    ///     .name("web")
+    ///     .preserve_fd(fd)
+    ///     .privileged_action(move || {
+    ///         let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    ///         // ... accept connections ...
+    ///         Ok(())
+    ///     })
+    ///     .start()?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    ///
+    /// Startup fails with an IO error if `fd` is not open.
+    #[cfg(unix)]
+    pub fn preserve_fd(mut self, fd: std::os::unix::io::RawFd) -> Self {
+        self.preserved_fds.push(fd);
+        self
+    }
+    #[cfg(not(unix))]
+    pub fn preserve_fd(self, _: i32) -> Self { self }
+
+    /// (Unix) In `foreground_run`, sends `SIGTERM` to the process when its parent exits.
+    ///
+    /// For supervised setups where the daemon must not outlive its supervisor. Uses
+    /// `PR_SET_PDEATHSIG` on Linux (re-armed after the privilege drop, which clears it)
+    /// and a `kqueue` `EVFILT_PROC` monitor thread on macOS and the BSDs. Has no effect
+    /// on detached daemons, whose parent is always init.
+    #[cfg(unix)] pub fn die_with_parent(mut self, enable: bool) -> Self { self.die_with_parent = enable; self }
+    #[cfg(not(unix))] pub fn die_with_parent(self, _: bool) -> Self { self }
+
+    /// (Unix) Selects how the daemon process is created. Defaults to `RelaunchMode::Fork`.
+    ///
+    /// `RelaunchMode::Respawn` starts a fresh copy of the current executable instead of
+    /// forking, which is safe for launchers that already run several threads. Ignored
+    /// under systemd, where the daemon stays in the foreground.
+    #[cfg(unix)] pub fn relaunch_mode(mut self, mode: RelaunchMode) -> Self { self.relaunch_mode = mode; self }
+
+    /// (Unix) Makes the daemon write a diagnostic dump to stderr (usually the log) when it
+    /// receives `signal`, typically `SIGUSR2` (`libc::SIGUSR2`).
+    ///
+    /// The dump holds the `health()` report and the configuration DaemonForge started
+    /// with, so operators can inspect a running daemon without attaching a debugger.
+    /// Environment variable values set with `.env()` are redacted; only their names appear.
+    /// Signals that can't be handled (`SIGKILL`, `SIGSEGV`, ...) make startup fail.
+    #[cfg(unix)] pub fn debug_signal(mut self, signal: i32) -> Self { self.debug_signal = Some(signal); self }
+    #[cfg(not(unix))] pub fn debug_signal(self, _: i32) -> Self { self }
+
+    /// (Unix) Delivers `signals` through a pipe instead of a callback, for daemons driving
+    /// their own event loop (epoll, mio).
+    ///
+    /// Each delivery writes the signal number as one byte to a non-blocking pipe whose read
+    /// end `daemon_forge::signal_pipe_fd()` returns; register it for readability and drain
+    /// it when it fires. Deliveries arriving while the pipe is full are dropped, so treat a
+    /// byte as "at least once since the last read". Installed before the privileged action;
+    /// signals that can't be handled (`SIGKILL`, `SIGSEGV`, ...) make startup fail.
+    #[cfg(unix)]
+    pub fn signal_pipe(mut self, signals: &[i32]) -> Self {
+        self.signal_pipe.extend_from_slice(signals);
+        self
+    }
+    #[cfg(not(unix))]
+    pub fn signal_pipe(self, _: &[i32]) -> Self { self }
+
+    /// (Unix) Enables in-place binary upgrades: when the daemon receives `signal` (typically
+    /// `SIGUSR1`) and the executable on disk differs from the one it started from (inode,
+    /// size or modification time), the daemon re-executes it under the same PID.
+    ///
+    /// The re-executed image calls `start()` again as usual; DaemonForge detects the
+    /// upgrade and skips forking, adopting the inherited instance lock instead of taking
+    /// it anew, so there is no window where a second instance could start. Other
+    /// descriptors survive only if they are not close-on-exec: clear `FD_CLOEXEC` on
+    /// listening sockets and pass their numbers on (e.g. in an environment variable).
+    ///
+    /// The new image runs with the privileges the daemon had at the time, so setup steps
+    /// needing root fail after a privilege drop. Not supported together with `chroot`,
+    /// where the executable is out of reach (`start()` fails with `ConfigError`).
+    #[cfg(unix)] pub fn reexec_on_upgrade(mut self, signal: i32) -> Self { self.reexec_on_upgrade = Some(signal); self }
+    #[cfg(not(unix))] pub fn reexec_on_upgrade(self, _: i32) -> Self { self }
+
+    /// (Unix) Sets the daemon's `SIGPIPE` disposition. Defaults to `SigpipeMode::Ignore`,
+    /// the Rust runtime's setting.
+    ///
+    /// Matters for daemons writing to peers that may disconnect (log shippers, proxies):
+    /// handle `EPIPE` errors, or let the daemon die with `SigpipeMode::Default`.
+    #[cfg(unix)] pub fn sigpipe(mut self, mode: SigpipeMode) -> Self { self.sigpipe = mode; self }
+    #[cfg(not(unix))] pub fn sigpipe(self, _: SigpipeMode) -> Self { self }
+
+    /// (Unix) If true and `in_container()` detects a container, `start()` stays in the
+    /// foreground (no double-fork), as under systemd.
+    ///
+    /// The container runtime supervises the main process directly: a daemonizing
+    /// entrypoint exits immediately and takes the container down with it. Combine with
+    /// `keep_all_stdio()` so the output still reaches the container logs.
+    #[cfg(unix)] pub fn container_aware(mut self, enable: bool) -> Self { self.container_aware = enable; self }
+    #[cfg(not(unix))] pub fn container_aware(self, _: bool) -> Self { self }
+    #[cfg(not(unix))] pub fn relaunch_mode(self, _: RelaunchMode) -> Self { self }
+
+    /// (Unix) If true, records how long each startup phase takes (fork, setsid, chdir,
+    /// chroot, PID file, privileged action, privilege drop).
+    ///
+    /// Read the result with `daemon_forge::startup_timings()`, e.g. to spot a slow NSS
+    /// `getpwnam` lookup. Phases run after the action (privilege drop) are only
+    /// available once the action has returned.
+    #[cfg(unix)] pub fn profile_startup(mut self, profile: bool) -> Self { self.profile_startup = profile; self }
+    #[cfg(not(unix))] pub fn profile_startup(self, _: bool) -> Self { self }
+
+    /// (Unix) If true (default), log files DaemonForge opens by path on the daemon's behalf
+    /// are opened with `O_NOFOLLOW`.
+    ///
+    /// Refuses to write through a symlink pre-created by an attacker (e.g. pointing at
+    /// `/etc/passwd`), a classic escalation vector for daemons started as root.
+    /// The PID file is always opened this way, regardless of this setting.
+    #[cfg(unix)] pub fn logs_nofollow(mut self, nofollow: bool) -> Self { self.logs_nofollow = nofollow; self }
+    #[cfg(not(unix))] pub fn logs_nofollow(self, _: bool) -> Self { self }
+
+    /// (Unix) If true (default), `SIGTERM` and `SIGINT` are blocked from before the fork
+    /// until the privileged action is about to run.
+    ///
+    /// A stop request that arrives mid-setup (between `chdir`, `chroot`, the PID file and
+    /// the privilege changes) is held pending instead of killing the process halfway, and
+    /// is delivered once the action starts. Install your handlers first thing in the action.
+    ///
+    /// Only the daemon keeps the signals blocked: the launching process gets its original
+    /// mask back right after the first fork (or, with `RelaunchMode::Respawn`, before
+    /// spawning the daemon, which starts with an empty mask and blocks them itself).
+    /// A `leader_election` standby unblocks them while it waits for the lock, so it can
+    /// still be stopped without `SIGKILL`.
+    #[cfg(unix)] pub fn block_signals_during_setup(mut self, block: bool) -> Self { self.block_signals_during_setup = block; self }
+    #[cfg(not(unix))] pub fn block_signals_during_setup(self, _: bool) -> Self { self }
+
+    /// (Unix) If true, a background thread periodically checks that the PID file is still
+    /// the one we locked and still holds our PID, and rewrites it otherwise.
+    ///
+    /// Guards against PID-file hijacking or accidental deletion on shared hosts. A file
+    /// that was replaced or removed is recreated (never through a symlink) and locked again,
+    /// unless another process already holds its lock. Only applies to detached and systemd
+    /// modes. The PID file's directory must stay writable after privileges are dropped
+    /// for replaced files to be recreated.
+    #[cfg(unix)] pub fn detect_pid_tampering(mut self, detect: bool) -> Self { self.detect_pid_tampering = detect; self }
+    #[cfg(not(unix))] pub fn detect_pid_tampering(self, _: bool) -> Self { self }
+
+    /// (Unix) Touches the PID file's modification time every `interval` from a background
+    /// thread, so an external monitor can tell a live daemon from a stale PID file by its age.
+    ///
+    /// Starts once the PID file is written and stops when it is removed (end of
+    /// `foreground_run`) or the process exits. The timestamp is set through the path
+    /// (`utimensat`, not following symlinks), which after the privilege drop requires the
+    /// daemon user to own the file: combine with `chown_pid_file(true)`. The thread keeps
+    /// ticking while the main loop hangs; pair with `hang_watchdog` to catch hangs.
+    #[cfg(unix)] pub fn pid_heartbeat(mut self, interval: Duration) -> Self { self.pid_heartbeat = Some(interval); self }
+    #[cfg(not(unix))] pub fn pid_heartbeat(self, _: Duration) -> Self { self }
+
+    /// (Unix) If true, re-reads the PID file after writing it and fails startup
+    /// unless it contains the PID of the final daemon process.
+    #[cfg(unix)] pub fn verify_pid_file(mut self, verify: bool) -> Self { self.verify_pid = verify; self }
+    #[cfg(not(unix))] pub fn verify_pid_file(self, _: bool) -> Self { self }
+
+    /// (Unix) Makes the launching process wait up to `duration` for the daemon
+    /// to finish redirecting its stdio before exiting.
+    ///
+    /// The launcher returns as soon as the redirection is confirmed, so the
+    /// shell prompt no longer interleaves with the daemon's startup output.
+    #[cfg(unix)] pub fn launcher_linger(mut self, duration: Duration) -> Self { self.launcher_linger = Some(duration); self }
+    #[cfg(not(unix))] pub fn launcher_linger(self, _: std::time::Duration) -> Self { self }
+
+    /// If true, the launching process waits until the daemon has finished its setup
+    /// (instance lock, PID file, chroot, privileged action, privilege drop, `on_ready`, ...)
+    /// and exits with status `1` if it failed, `0` once all of it succeeded. Applies to
+    /// both `RelaunchMode`s; with `Respawn`, the daemon gets the pipe it reports on through
+    /// an inherited descriptor.
+    ///
+    /// On Windows, a failed setup instead makes `start()` return an error of the same
+    /// category (`TargetLocked`, `ConfigError`, ...) in the launcher.
+    ///
+    /// Lets init scripts rely on `$?` after launching. Ignored in systemd and foreground modes.
+    pub fn launcher_exit_on_child_failure(mut self, enable: bool) -> Self { self.launcher_exit_on_failure = enable; self }
+
+    /// Decomposes the builder into its resolved configuration.
+    ///
+    /// For advanced consumers implementing their own `start` sequence on top of the
+    /// configuration DaemonForge already parsed. Options without a field in `DaemonParts`
+    /// are dropped.
+    pub fn into_parts(mut self) -> DaemonParts<SetupOutput> {
+        self.resolve_env();
+
+        DaemonParts {
+            pid_file: self.lock_path(),
+            name: self.name,
+            working_directory: self.directory,
+            stdin: self.stdin,
+            stdout: self.stdout,
+            stderr: self.stderr,
+            clear_env: self.clear_env,
+            env: self.env_vars,
+            #[cfg(unix)] lock_file: self.lock_file,
+            #[cfg(unix)] user: self.user,
+            #[cfg(unix)] group: self.group,
+            #[cfg(unix)] umask: self.umask,
+            #[cfg(unix)] root: self.root,
+            before_fork: self.before_fork,
+            on_setup_failure: self.on_setup_failure,
+            privileged_action: self.privileged_action.unwrap(),
+        }
+    }
+
+    /// Refuses to relaunch the executable unless its SHA-256 digest matches `expected_sha256`
+    /// (hex, case-insensitive).
+    ///
+    /// Applies where DaemonForge re-executes `current_exe`: always on Windows, and with
+    /// `RelaunchMode::Respawn` on Unix. The file is hashed right before spawning; a mismatch
+    /// or a malformed digest fails `start()` with `DaemonError::ConfigError`.
+    pub fn verify_exe_hash(mut self, expected_sha256: &str) -> Self {
+        self.exe_sha256 = Some(expected_sha256.trim().to_ascii_lowercase());
+        self
+    }
+
     /// Starts the daemonization process.
+    ///
+    /// Fails with `DaemonError::ConfigError` if nothing identifies the instance lock
+    /// (see `allow_no_lock`).
     pub fn start(self) -> DaemonResult<SetupOutput> {
+        match self.start_with(true)? {
+            StartState::Daemon { setup } => Ok(setup),
+            StartState::Launcher { .. } => unreachable!("the launcher exits inside start()"),
+        }
+    }
+
+    /// Like `start()`, but the launching process returns too, with
+    /// `StartState::Launcher` and the daemon's PID, instead of exiting.
+    ///
+    /// Lets one code path tell the roles apart, e.g. to print the PID or wait for the
+    /// daemon's socket before exiting. Where no launcher is involved (systemd,
+    /// `container_aware`) only `StartState::Daemon` is returned. With
+    /// `launcher_exit_on_child_failure`, a daemon failing its setup makes the launcher
+    /// return an error instead of exiting with status `1`. With `RelaunchMode::Respawn`
+    /// and on Windows the daemon is a direct child of the launcher, which should exit
+    /// rather than outlive it. To see how far a failed setup got, enable `profile_startup`.
+    pub fn try_start(self) -> DaemonResult<StartState<SetupOutput>> {
+        self.start_with(false)
+    }
+
+    fn start_with(mut self, exit_launcher: bool) -> DaemonResult<StartState<SetupOutput>> {
+        self.check_required_user()?;
+        self.check_lock_identity()?;
+        self.resolve_env();
+
+        #[cfg(unix)]
+        return crate::sys::unix::start(self, exit_launcher);
+
+        #[cfg(windows)]
+        return crate::sys::windows::start(self, exit_launcher);
+    }
+
+    /// Stops the running instance: reads its PID from the PID file (resolved like
+    /// `effective_pid_path()`) and sends it `SIGTERM`, or `SIGKILL` if `force` is set.
+    /// On Windows the process is terminated with `TerminateProcess` either way.
+    ///
+    /// Returns once the signal is sent, without waiting for the daemon to exit. Fails with
+    /// `DaemonError::Io` (`NotFound`) if there is no PID file and `DaemonError::ConfigError`
+    /// if none is configured. If the recorded process no longer exists, the stale PID file
+    /// is removed and `Ok(())` is returned.
+    pub fn stop(&self, force: bool) -> DaemonResult<()> {
+        let path = self.effective_pid_path()
+            .ok_or_else(|| DaemonError::ConfigError("stop() requires a pid_file or name".to_owned()))?;
+        let content = std::fs::read_to_string(&path).map_err(|e| DaemonError::io("read PID file", e))?;
+        let pid = content.trim().parse::<u32>().map_err(|_| DaemonError::io("read PID file", std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("PID file {:?} contains '{}'", path, content.trim()),
+        )))?;
+
+        #[cfg(unix)]
+        let running = crate::sys::unix::terminate(pid, force)?;
+        #[cfg(windows)]
+        let running = crate::sys::windows::terminate(pid, force)?;
+
+        if !running {
+            std::fs::remove_file(&path).or_else(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(DaemonError::io("remove stale PID file", e)),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Reports whether an instance of this daemon is running, for `status` subcommands.
+    ///
+    /// - **Unix:** reads the PID file (`effective_pid_path()`) and probes the PID with
+    ///   `kill(pid, 0)`. A file that does not parse as a PID counts as stale.
+    /// - **Windows:** checks whether the global mutex (`windows_mutex_name()`) exists,
+    ///   and reads the PID from the PID file, if any. `Running(None)` means the instance
+    ///   holds the mutex but its PID could not be read.
+    ///
+    /// Returns `ConfigError` if the daemon has neither a `pid_file` nor a `name`.
+    pub fn status(&self) -> DaemonResult<DaemonStatus> {
+        let read_pid = |path: &Path| -> DaemonResult<Option<u32>> {
+            match std::fs::read_to_string(path) {
+                Ok(content) => Ok(content.trim().parse::<u32>().ok()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(DaemonError::io("read PID file", e)),
+            }
+        };
+
+        #[cfg(unix)]
+        {
+            let path = self.effective_pid_path()
+                .ok_or_else(|| DaemonError::ConfigError("status() requires a pid_file or name".to_owned()))?;
+            if !path.exists() {
+                return Ok(DaemonStatus::Stopped);
+            }
+            Ok(match read_pid(&path)? {
+                Some(pid) if crate::sys::unix::pid_is_alive(pid) => DaemonStatus::Running(Some(pid)),
+                _ => DaemonStatus::StalePidFile,
+            })
+        }
+
+        #[cfg(windows)]
+        {
+            let name = self.windows_mutex_name()
+                .ok_or_else(|| DaemonError::ConfigError("status() requires a pid_file or name".to_owned()))?;
+            let path = self.effective_pid_path();
+            if crate::sys::windows::mutex_exists(&name)? {
+                // The mutex alone proves the instance runs; its PID is a bonus
+                let pid = path.as_deref().and_then(|path| read_pid(path).ok().flatten());
+                return Ok(DaemonStatus::Running(pid));
+            }
+            Ok(match path.is_some_and(|path| path.exists()) {
+                true => DaemonStatus::StalePidFile,
+                false => DaemonStatus::Stopped,
+            })
+        }
+    }
+
+    /// Daemonizes like `start()`, then runs `f` with the privileged action's output in the
+    /// daemon and exits when it returns. Never returns, in either process.
+    ///
+    /// For the common "daemonize, then loop until told to stop" pattern. The launcher exits
+    /// with `0` once the daemon is detached. The daemon exits with `0` when `f` returns
+    /// `Ok`; an `Err` from `f` or from the setup is written to stderr (the daemon's log, or
+    /// the terminal if the launcher failed) and mapped to an exit code: `70` locked,
+    /// `71` privileges, `72` environment, `73` configuration, `74` IO, `75` system call
+    /// (Unix) or Win32 (Windows).
+    pub fn run<F>(self, f: F) -> !
+    where
+        F: FnOnce(SetupOutput) -> DaemonResult<()>,
+    {
+        match self.start().and_then(f) {
+            Ok(()) => std::process::exit(0),
+            Err(e) => {
+                eprintln!("{}", critical_line(&format!("Daemon failed: {}", e)));
+                std::process::exit(e.exit_code());
+            }
+        }
+    }
+
+    /// Runs the daemon in the foreground, without forking or detaching.
+    ///
+    /// Applies the working directory, umask, environment, locking and privilege
+    /// drop exactly like `start()`, then calls `f` with the privileged action's
+    /// output and runs it to completion. The PID file and lock are cleaned up
+    /// when `f` returns.
+    ///
+    /// This is the entry point for containers and other supervisors that expect
+    /// the service to stay attached (e.g. Docker, Kubernetes).
+    pub fn foreground_run<R, F>(mut self, f: F) -> DaemonResult<R>
+    where
+        F: FnOnce(SetupOutput) -> DaemonResult<R>,
+    {
+        self.check_required_user()?;
+        self.resolve_env();
+
         #[cfg(unix)]
-        return crate::sys::unix::start(self);
+        return crate::sys::unix::foreground_run(self, f);
 
         #[cfg(windows)]
-        return crate::sys::windows::start(self);
+        return crate::sys::windows::foreground_run(self, f);
     }
 
+    /// Checks `exe` against the `verify_exe_hash` digest, if one is configured.
+    pub(crate) fn check_exe_hash(&self, exe: &Path) -> DaemonResult<()> {
+        let Some(expected) = &self.exe_sha256 else {
+            return Ok(());
+        };
+        if expected.len() != 64 || !expected.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(DaemonError::ConfigError(format!("Invalid SHA-256 digest: '{}'", expected)));
+        }
+
+        let actual = std::fs::File::open(exe)
+            .and_then(crate::sha256::hex_digest)
+            .map_err(|e| DaemonError::io("hash executable", e))?;
+        if &actual != expected {
+            return Err(DaemonError::ConfigError(format!(
+                "Executable {:?} has SHA-256 {}, expected {}",
+                exe, actual, expected
+            )));
+        }
+        Ok(())
+    }
+
+    /// Performs the `inherit_env_deferred` capture and applies `env_precedence`
+    /// to the inherited keys.
+    fn resolve_env(&mut self) {
+        if self.inherit_env_deferred {
+            for (k, v) in std::env::vars() {
+                self.env_vars.entry(k.clone()).or_insert_with(|| v.clone());
+                self.inherited_env.insert(k, v);
+            }
+        }
+
+        if self.env_precedence == EnvPrecedence::InheritedWins {
+            for (k, v) in &self.inherited_env {
+                self.env_vars.insert(k.clone(), v.clone());
+            }
+        }
+    }
+
+    /// Writes the current process environment to `dump_env_to`, if configured.
+    pub(crate) fn dump_env(&self) -> DaemonResult<()> {
+        let Some(path) = &self.dump_env_to else { return Ok(()) };
+
+        let mut vars: Vec<_> = std::env::vars_os()
+            .map(|(k, v)| format!("{}={}\n", k.to_string_lossy(), v.to_string_lossy()))
+            .collect();
+        vars.sort();
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options.open(path).map_err(|e| DaemonError::io("open env dump", e))?;
+        file.write_all(vars.concat().as_bytes()).map_err(|e| DaemonError::io("write env dump", e))
+    }
+
+    /// Verifies that every `require_env` variable is set in the final environment.
+    pub(crate) fn check_required_env(&self) -> DaemonResult<()> {
+        let missing = self.missing_env();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(DaemonError::EnvError(format!(
+                "Missing required environment variables: {}",
+                missing.join(", ")
+            )))
+        }
+    }
+
+    /// The `require_env` variables absent (or empty) in the final environment.
+    fn missing_env(&self) -> Vec<&str> {
+        self
+            .required_env
+            .iter()
+            .filter(|key| {
+                let value = match self.env_vars.get(key.as_str()) {
+                    Some(v) => Some(v.clone()),
+                    None if self.clear_env => None,
+                    None => std::env::var(key.as_str()).ok(),
+                };
+                value.is_none_or(|v| v.is_empty())
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Name of the variable marking the relaunched daemon process (`internal_flag_name`).
+    pub(crate) fn internal_flag(&self) -> DaemonResult<&str> {
+        match self.internal_flag_name.as_deref() {
+            None => Ok(DEFAULT_INTERNAL_FLAG),
+            Some(name) if !name.is_empty() && !name.contains(['=', '\0']) => Ok(name),
+            Some(name) => Err(DaemonError::ConfigError(format!(
+                "internal_flag_name {:?} is not a valid environment variable name",
+                name
+            ))),
+        }
+    }
+
+    /// The PID/lock file path the platform backend will use.
+    ///
+    /// On Unix, a daemon with only a `name` falls back to `daemon-<name>.pid` in the
+    /// `pid_fallback_dir` (the temp directory by default).
+    pub(crate) fn lock_path(&self) -> Option<PathBuf> {
+        if let Some(path) = &self.pid_file {
+            return Some(path.clone());
+        }
+
+        #[cfg(unix)]
+        return self.name.as_ref().map(|name| {
+            let dir = self.pid_fallback_dir.clone().unwrap_or_else(std::env::temp_dir);
+            dir.join(format!("daemon-{}.pid", name))
+        });
+
+        #[cfg(not(unix))]
+        None
+    }
+
+    /// Maps a path as seen by the daemon (after `chdir` and `chroot`) to its location
+    /// as seen by the launching process.
+    pub(crate) fn resolve_path(&self, path: &Path) -> PathBuf {
+        #[cfg(unix)]
+        if let Some(root) = &self.root
+            && (path.is_absolute() || self.chroot_chdir)
+        {
+            return root.join(path.strip_prefix("/").unwrap_or(path));
+        }
+
+        if path.is_relative() {
+            self.directory.join(path)
+        } else {
+            path.to_path_buf()
+        }
+    }
+
+    /// Writes a `critical_line` to the stderr log file, or the stdout one if stderr
+    /// doesn't go to a file, for `last_error_from_log`.
     pub(crate) fn log_error(&mut self, msg: &str) {
-        let msg_formatted = format!("[DaemonForge Critical] {}", msg);
-        if let Stdio::RedirectToFile(ref mut f) = self.stderr {
-             let _ = writeln!(f, "{}", msg_formatted);
-             let _ = f.sync_all();
-        } 
-        else if let Stdio::RedirectToFile(ref mut f) = self.stdout {
-             let _ = writeln!(f, "{}", msg_formatted);
-             let _ = f.sync_all();
+        let msg_formatted = critical_line(msg);
+        let to_stderr = matches!(self.stderr, Stdio::RedirectToFile(_) | Stdio::RedirectToPath(_));
+        match if to_stderr { &mut self.stderr } else { &mut self.stdout } {
+            Stdio::RedirectToFile(f) => {
+                let _ = writeln!(f, "{}", msg_formatted);
+                let _ = f.sync_all();
+            }
+            // Already opened onto the standard stream, which still reaches it inside a `chroot`
+            #[cfg(unix)]
+            Stdio::RedirectToPath(_) if to_stderr => eprintln!("{}", msg_formatted),
+            #[cfg(unix)]
+            Stdio::RedirectToPath(_) => println!("{}", msg_formatted),
+            #[cfg(not(unix))]
+            Stdio::RedirectToPath(path) => {
+                if let Ok(mut f) = crate::stdio::open_path(path, false, false) {
+                    let _ = writeln!(f, "{}", msg_formatted);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Parses a symbolic (`"u=rwx,g=rx,o="`) or octal (`"027"`) umask spec.
+#[cfg(unix)]
+fn parse_symbolic_umask(spec: &str, current: u32) -> Option<u32> {
+    let spec = spec.trim();
+    let octal = spec.strip_prefix("0o").unwrap_or(spec);
+    if !octal.is_empty() && octal.chars().all(|c| c.is_digit(8)) {
+        return u32::from_str_radix(octal, 8).ok().filter(|m| *m <= 0o777);
+    }
+
+    // Work with the permissions to keep; the umask is their complement
+    let mut allowed = !current & 0o777;
+    for clause in spec.split(',') {
+        let op_pos = clause.find(['=', '+', '-'])?;
+        let (who, rest) = clause.split_at(op_pos);
+        let (op, perms) = rest.split_at(1);
+
+        let mut who_bits = 0;
+        for c in who.chars() {
+            who_bits |= match c {
+                'u' => 0o700,
+                'g' => 0o070,
+                'o' => 0o007,
+                'a' => 0o777,
+                _ => return None,
+            };
+        }
+        if who_bits == 0 {
+            who_bits = 0o777;
+        }
+
+        let mut perm_bits = 0;
+        for c in perms.chars() {
+            perm_bits |= match c {
+                'r' => 0o444,
+                'w' => 0o222,
+                'x' => 0o111,
+                _ => return None,
+            };
+        }
+        let bits = who_bits & perm_bits;
+
+        match op {
+            "=" => allowed = (allowed & !who_bits) | bits,
+            "+" => allowed |= bits,
+            _ => allowed &= !bits,
         }
     }
-}
\ No newline at end of file
+
+    Some(!allowed & 0o777)
+}
+
+/// Returns `true` if the current process may write to `path`.
+fn is_writable(path: &Path) -> bool {
+    #[cfg(unix)]
+    return crate::sys::unix::is_writable(path);
+
+    #[cfg(windows)]
+    return std::fs::metadata(path).is_ok_and(|m| !m.permissions().readonly());
+}
+
+/// The closest ancestor of `path` (including itself) that exists.
+fn nearest_existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|p| p.exists())
+}
+
+/// Escapes `%` specifiers so systemd takes the value literally.
+fn escape_unit_value(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+/// Quotes a single word for `ExecStart=`/`Environment=` when it contains whitespace,
+/// quotes or backslashes.
+fn quote_unit_word(word: &str) -> String {
+    let word = escape_unit_value(word);
+    if word.is_empty() || word.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\' || c == '\'') {
+        format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        word
+    }
+}
+
+/// Runs the `on_setup_failure` hook for `err`; its own failure is only written to stderr.
+pub(crate) fn run_setup_failure_hook(hook: Option<Box<dyn FnOnce() -> DaemonResult<()>>>, err: &DaemonError) {
+    if let Some(Err(hook_err)) = hook.map(|hook| hook()) {
+        eprintln!("[DaemonForge] on_setup_failure hook failed: {} (setup error: {})", hook_err, err);
+    }
+}
+
+/// Default variable marking the relaunched daemon process.
+const DEFAULT_INTERNAL_FLAG: &str = "__DAEMONIZED_INTERNAL_FLAG";
+
+/// 64-bit FNV-1a hash. Deterministic across runs and builds, unlike `DefaultHasher`.
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, b| (hash ^ *b as u64).wrapping_mul(PRIME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders `mask` the way `umask -S` does: the permissions each class keeps.
+    #[cfg(unix)]
+    fn umask_s(mask: u32) -> String {
+        let allowed = !mask & 0o777;
+        ["u", "g", "o"]
+            .iter()
+            .enumerate()
+            .map(|(i, who)| {
+                let bits = (allowed >> (6 - 3 * i)) & 0o7;
+                let perms: String = [(0o4, 'r'), (0o2, 'w'), (0o1, 'x')]
+                    .iter()
+                    .filter(|(bit, _)| bits & bit != 0)
+                    .map(|(_, c)| *c)
+                    .collect();
+                format!("{}={}", who, perms)
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn umask_octal() {
+        assert_eq!(parse_symbolic_umask("077", 0o022), Some(0o077));
+        assert_eq!(parse_symbolic_umask("0022", 0o077), Some(0o022));
+        assert_eq!(parse_symbolic_umask("0o027", 0o022), Some(0o027));
+        assert_eq!(parse_symbolic_umask(" 0 ", 0o022), Some(0));
+        assert_eq!(parse_symbolic_umask("1777", 0o022), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn umask_symbolic_absolute() {
+        assert_eq!(parse_symbolic_umask("u=rwx,g=rx,o=", 0o022), Some(0o027));
+        assert_eq!(parse_symbolic_umask("u=rwx,go=", 0o022), Some(0o077));
+        assert_eq!(parse_symbolic_umask("a=", 0o022), Some(0o777));
+        assert_eq!(parse_symbolic_umask("=rx", 0o022), Some(0o222));
+        assert_eq!(parse_symbolic_umask("a=rwx", 0o077), Some(0));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn umask_symbolic_relative() {
+        assert_eq!(parse_symbolic_umask("g+w", 0o022), Some(0o002));
+        assert_eq!(parse_symbolic_umask("o-r", 0o022), Some(0o026));
+        assert_eq!(parse_symbolic_umask("g-w", 0o022), Some(0o022));
+        assert_eq!(parse_symbolic_umask("u=rwx,o-rx", 0o022), Some(0o027));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn umask_symbolic_round_trips_umask_s() {
+        for mask in 0..=0o777 {
+            assert_eq!(parse_symbolic_umask(&umask_s(mask), 0o022), Some(mask), "{}", umask_s(mask));
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn umask_invalid() {
+        for spec in ["", "u", "x=r", "u=rwz", "u=rwx;g=rx", "u=rwx,,g=rx", "u=rwx,", "888", "0o8"] {
+            assert_eq!(parse_symbolic_umask(spec, 0o022), None, "{:?}", spec);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn systemd_unit_template_translates_config() {
+        let daemon = ForgeDaemon::new()
+            .name("ticker")
+            .user("ticker")
+            .group("daemon")
+            .umask(0o027)
+            .env("GREETING", "hello world")
+            .env("QUOTE", "say \"hi\"")
+            .env("RATE", "100%")
+            .pid_file("/run/ticker.pid");
+
+        let unit = daemon.systemd_unit_template(false);
+        let service: Vec<&str> = unit.lines().skip_while(|l| *l != "[Service]").collect();
+        for line in [
+            "Type=forking",
+            "User=ticker",
+            "Group=daemon",
+            "UMask=0027",
+            "Environment=\"GREETING=hello world\"",
+            "Environment=\"QUOTE=say \\\"hi\\\"\"",
+            "Environment=RATE=100%%",
+            "PIDFile=/run/ticker.pid",
+        ] {
+            assert!(service.contains(&line), "{:?} missing from:\n{}", line, unit);
+        }
+
+        let unit = daemon.systemd_unit_template(true);
+        assert!(unit.lines().any(|l| l == "Type=simple"), "{}", unit);
+    }
+
+    #[test]
+    fn missing_lock_identity_is_a_config_error() {
+        let err = ForgeDaemon::new().build().unwrap_err();
+        assert!(matches!(err, DaemonError::ConfigError(_)), "{:?}", err);
+        // Checked before anything forks
+        let err = ForgeDaemon::new().start().unwrap_err();
+        assert!(matches!(err, DaemonError::ConfigError(_)), "{:?}", err);
+
+        assert!(ForgeDaemon::new().allow_no_lock(true).build().is_ok());
+        assert!(ForgeDaemon::new().name("daemon_forge_test").build().is_ok());
+        #[cfg(unix)]
+        assert!(ForgeDaemon::new().lock_file("/tmp/daemon_forge_test.lock").build().is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn umask_symbolic_builder_reports_config_error() {
+        let err = ForgeDaemon::new().umask_symbolic("u=rwz").unwrap_err();
+        assert!(matches!(err, DaemonError::ConfigError(_)));
+
+        let daemon = ForgeDaemon::new().umask(0o077).umask_symbolic("g+rx").unwrap();
+        assert_eq!(daemon.umask, Some(0o027));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn resolve_path_applies_working_directory() {
+        let daemon = ForgeDaemon::new().working_directory("/srv/app");
+        assert_eq!(daemon.resolve_path(Path::new("run/app.pid")), Path::new("/srv/app/run/app.pid"));
+        assert_eq!(daemon.resolve_path(Path::new("/var/run/app.pid")), Path::new("/var/run/app.pid"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn resolve_path_maps_into_chroot() {
+        let daemon = ForgeDaemon::new().working_directory("/srv/app").chroot("/jail");
+        assert_eq!(daemon.resolve_path(Path::new("/run/app.pid")), Path::new("/jail/run/app.pid"));
+        // After the `chdir("/")` into the jail, relative paths start at its root
+        assert_eq!(daemon.resolve_path(Path::new("app.pid")), Path::new("/jail/app.pid"));
+
+        let daemon = daemon.chroot_chdir(false);
+        assert_eq!(daemon.resolve_path(Path::new("/run/app.pid")), Path::new("/jail/run/app.pid"));
+        assert_eq!(daemon.resolve_path(Path::new("app.pid")), Path::new("/srv/app/app.pid"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn effective_pid_path_resolves_fallback_chroot_and_relative_paths() {
+        assert_eq!(ForgeDaemon::new().effective_pid_path(), None);
+
+        let daemon = ForgeDaemon::new().name("ticker").pid_fallback_dir("/run");
+        assert_eq!(daemon.effective_pid_path(), Some(PathBuf::from("/run/daemon-ticker.pid")));
+        let daemon = daemon.chroot("/jail");
+        assert_eq!(daemon.effective_pid_path(), Some(PathBuf::from("/jail/run/daemon-ticker.pid")));
+
+        // An explicit PID file takes precedence over the name
+        let daemon = daemon.pid_file("/var/run/ticker.pid");
+        assert_eq!(daemon.effective_pid_path(), Some(PathBuf::from("/jail/var/run/ticker.pid")));
+
+        // Still relative after resolution: made absolute against the current directory
+        let daemon = ForgeDaemon::new().working_directory("work").pid_file("ticker.pid");
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(daemon.effective_pid_path(), Some(cwd.join("work").join("ticker.pid")));
+    }
+
+    /// Set in every test environment, so `inherit_env` always captures it.
+    const INHERITED: &str = "PATH";
+
+    #[test]
+    fn explicit_env_wins_regardless_of_call_order() {
+        let inherited = std::env::var(INHERITED).unwrap();
+        assert_ne!(inherited, "/explicit");
+
+        let mut before = ForgeDaemon::new().env(INHERITED, "/explicit").inherit_env();
+        let mut after = ForgeDaemon::new().inherit_env().env(INHERITED, "/explicit");
+        for daemon in [&mut before, &mut after] {
+            daemon.resolve_env();
+            assert_eq!(daemon.env_vars[INHERITED], "/explicit");
+        }
+    }
+
+    #[test]
+    fn inherited_env_wins_regardless_of_call_order() {
+        let inherited = std::env::var(INHERITED).unwrap();
+
+        let mut before = ForgeDaemon::new().env(INHERITED, "/explicit").inherit_env();
+        let mut after = ForgeDaemon::new().inherit_env().env(INHERITED, "/explicit");
+        for daemon in [&mut before, &mut after] {
+            daemon.env_precedence = EnvPrecedence::InheritedWins;
+            daemon.resolve_env();
+            assert_eq!(daemon.env_vars[INHERITED], inherited);
+        }
+
+        // Keys only set explicitly are kept
+        let mut daemon = ForgeDaemon::new()
+            .env("DAEMON_FORGE_TEST_EXPLICIT_ONLY", "1")
+            .inherit_env()
+            .env_precedence(EnvPrecedence::InheritedWins);
+        daemon.resolve_env();
+        assert_eq!(daemon.env_vars["DAEMON_FORGE_TEST_EXPLICIT_ONLY"], "1");
+    }
+
+    #[test]
+    fn missing_env_reports_unset_and_empty_variables() {
+        let daemon = ForgeDaemon::new()
+            .env("DAEMON_FORGE_TEST_SET", "1")
+            .env("DAEMON_FORGE_TEST_EMPTY", "")
+            .require_env(&["DAEMON_FORGE_TEST_SET", "DAEMON_FORGE_TEST_EMPTY", "DAEMON_FORGE_TEST_UNSET", INHERITED]);
+        assert_eq!(daemon.missing_env(), ["DAEMON_FORGE_TEST_EMPTY", "DAEMON_FORGE_TEST_UNSET"]);
+        assert!(daemon.check_required_env().is_err());
+
+        // Nothing is inherited into a cleared environment
+        let daemon = daemon.clear_env(true);
+        assert_eq!(daemon.missing_env(), ["DAEMON_FORGE_TEST_EMPTY", "DAEMON_FORGE_TEST_UNSET", INHERITED]);
+
+        assert!(ForgeDaemon::new().require_env(&[INHERITED]).missing_env().is_empty());
+    }
+
+    #[test]
+    fn critical_line_carries_marker_and_timestamp() {
+        let line = critical_line("boom");
+        let rest = line.strip_prefix("[DaemonForge Critical] [").unwrap();
+        let (ts, msg) = rest.split_once("] ").unwrap();
+        assert!(!ts.is_empty() && ts.chars().all(|c| c.is_ascii_digit()), "{}", line);
+        assert_eq!(msg, "boom");
+    }
+
+    #[test]
+    fn last_error_from_log_returns_the_latest_critical_message() {
+        let path = std::env::temp_dir().join(format!("daemon_forge_test_{}_critical.log", std::process::id()));
+        let log = [
+            "starting".to_owned(),
+            critical_line("Failed to acquire instance lock. Daemon is already running"),
+            "[DaemonForge Critical] written before timestamps were added".to_owned(),
+            critical_line("Daemon setup failed. [bracketed] detail"),
+            "shutting down".to_owned(),
+            String::new(),
+        ];
+        std::fs::write(&path, log.join("\n")).unwrap();
+        assert_eq!(ForgeDaemon::last_error_from_log(&path).as_deref(), Some("Daemon setup failed. [bracketed] detail"));
+
+        std::fs::write(&path, log[..3].join("\n")).unwrap();
+        assert_eq!(
+            ForgeDaemon::last_error_from_log(&path).as_deref(),
+            Some("written before timestamps were added")
+        );
+
+        std::fs::write(&path, "starting\nshutting down\n").unwrap();
+        assert_eq!(ForgeDaemon::last_error_from_log(&path), None);
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ForgeDaemon::last_error_from_log(&path), None);
+    }
+
+    #[test]
+    fn fnv1a_64_matches_reference_vectors() {
+        assert_eq!(fnv1a_64(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a_64(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a_64(b"foobar"), 0x85944171f73967e8);
+    }
+}
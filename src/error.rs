@@ -1,18 +1,22 @@
 use std::fmt;
 use std::io;
+use std::path::PathBuf;
 
 /// Custom error type for DaemonForge.
 /// Provides specific details about why the daemonization failed.
 #[derive(Debug)]
 pub enum DaemonError {
     /// Standard IO errors (file creation, piping, etc.)
-    Io(io::Error),
+    /// `context` names the operation that failed (e.g. `"chdir"`), or is empty if unknown.
+    Io { context: &'static str, source: io::Error },
     /// The PID lock file or Mutex is already locked by another instance.
     TargetLocked,
     /// Failed to drop privileges (User/Group not found or permission denied).
     PrivilegeError(String),
     /// Environment variable error (e.g., failed to set or clear).
     EnvError(String),
+    /// Invalid builder configuration (e.g., a malformed umask specification).
+    ConfigError(String),
     /// (Windows) Specific Win32 API error code.
     #[cfg(not(unix))]
     Win32Error(u32),
@@ -24,10 +28,14 @@ pub enum DaemonError {
 impl fmt::Display for DaemonError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            DaemonError::Io(err) => write!(f, "IO Error: {}", err),
+            DaemonError::Io { context: "", source } => write!(f, "IO Error: {}", source),
+            DaemonError::Io { context, source } => {
+                write!(f, "IO Error during '{}': {}", context, source)
+            }
             DaemonError::TargetLocked => write!(f, "Daemon is already running (Target Locked)"),
             DaemonError::PrivilegeError(msg) => write!(f, "Privilege Drop Error: {}", msg),
             DaemonError::EnvError(msg) => write!(f, "Environment Error: {}", msg),
+            DaemonError::ConfigError(msg) => write!(f, "Configuration Error: {}", msg),
             #[cfg(not(unix))]
             DaemonError::Win32Error(code) => write!(f, "Win32 API Error Code: {}", code),
             #[cfg(unix)]
@@ -41,15 +49,85 @@ impl fmt::Display for DaemonError {
 impl std::error::Error for DaemonError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            DaemonError::Io(err) => Some(err),
+            DaemonError::Io { source, .. } => Some(source),
             _ => None,
         }
     }
 }
 
+impl DaemonError {
+    /// Wraps an IO error with the name of the operation that produced it.
+    pub fn io(context: &'static str, source: io::Error) -> Self {
+        DaemonError::Io { context, source }
+    }
+
+    /// Process exit code reporting the error's category (see `ForgeDaemon::run`).
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            DaemonError::TargetLocked => EXIT_TARGET_LOCKED,
+            DaemonError::PrivilegeError(_) => EXIT_PRIVILEGE,
+            DaemonError::EnvError(_) => EXIT_ENV,
+            DaemonError::ConfigError(_) => EXIT_CONFIG,
+            DaemonError::Io { .. } => EXIT_IO,
+            #[cfg(not(unix))]
+            DaemonError::Win32Error(_) => EXIT_OS,
+            #[cfg(unix)]
+            DaemonError::SyscallError { .. } => EXIT_OS,
+        }
+    }
+}
+
+// Exit codes of a daemon that failed: read back by the Windows launcher
+// (`launcher_exit_on_child_failure`) and used by `ForgeDaemon::run`
+pub(crate) const EXIT_TARGET_LOCKED: i32 = 70;
+pub(crate) const EXIT_PRIVILEGE: i32 = 71;
+pub(crate) const EXIT_ENV: i32 = 72;
+pub(crate) const EXIT_CONFIG: i32 = 73;
+pub(crate) const EXIT_IO: i32 = 74;
+pub(crate) const EXIT_OS: i32 = 75;
+
 impl From<io::Error> for DaemonError {
     fn from(err: io::Error) -> Self {
-        DaemonError::Io(err)
+        DaemonError::Io { context: "", source: err }
+    }
+}
+
+/// A single issue reported by `ForgeDaemon::validate_permissions()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigProblem {
+    /// The working directory does not exist or is not a directory.
+    WorkingDirectoryMissing(PathBuf),
+    /// The PID file's directory is missing (and won't be created) or is not writable.
+    PidDirectoryNotWritable(PathBuf),
+    /// The chroot directory does not exist or is not a directory.
+    ChrootMissing(PathBuf),
+    /// The runtime directory does not exist and can't be created.
+    RuntimeDirectoryNotCreatable(PathBuf),
+    /// The target user could not be resolved.
+    UserNotFound(String),
+    /// The target group could not be resolved.
+    GroupNotFound(String),
+    /// A redirected output stream (`"stdout"`/`"stderr"`) is not open for writing.
+    OutputNotWritable(&'static str),
+    /// (Unix) A `Stdio::file` log can't be written, or created, by the user the daemon runs as.
+    LogFileNotWritable(PathBuf),
+    /// A `require_env` variable is missing from the final environment.
+    MissingEnv(String),
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigProblem::WorkingDirectoryMissing(p) => write!(f, "Working directory {:?} does not exist", p),
+            ConfigProblem::PidDirectoryNotWritable(p) => write!(f, "PID directory {:?} is not writable", p),
+            ConfigProblem::ChrootMissing(p) => write!(f, "Chroot directory {:?} does not exist", p),
+            ConfigProblem::RuntimeDirectoryNotCreatable(p) => write!(f, "Runtime directory {:?} can't be created", p),
+            ConfigProblem::UserNotFound(u) => write!(f, "User '{}' not found", u),
+            ConfigProblem::GroupNotFound(g) => write!(f, "Group '{}' not found", g),
+            ConfigProblem::OutputNotWritable(s) => write!(f, "{} is not open for writing", s),
+            ConfigProblem::LogFileNotWritable(p) => write!(f, "Log file {:?} is not writable by the daemon's user", p),
+            ConfigProblem::MissingEnv(k) => write!(f, "Required environment variable '{}' is missing", k),
+        }
     }
 }
 
@@ -7,14 +7,31 @@
 //!
 
 
+#[cfg(unix)]
+mod activation;
 mod daemon;
 mod error;
+mod notify;
+mod process;
+mod sha256;
+mod shutdown;
 mod stdio;
 mod sys;
 mod types;
+mod watchdog;
 
 // Re-export public types to keeping the API flat
-pub use daemon::ForgeDaemon;
-pub use error::{DaemonError, DaemonResult};
-pub use stdio::Stdio;
-pub use types::{Group, User};
+#[cfg(unix)]
+pub use activation::listen_fds_with_names;
+pub use daemon::{DaemonParts, ForgeDaemon};
+pub use error::{ConfigProblem, DaemonError, DaemonResult};
+pub use notify::{notify_ready_fd, READY_FD_VAR};
+pub use process::{health, in_container, pid_is_alive, recorded_pid, startup_timings, take_reload_request, HealthReport};
+#[cfg(unix)]
+pub use process::{drop_privileges, regain_privileges, signal_pipe_fd, take_lock_fd};
+#[cfg(windows)]
+pub use process::inherited_handles;
+pub use shutdown::{is_shutdown_requested, ShutdownGuard};
+pub use stdio::{update_stdio_paths, Stdio};
+pub use types::{DaemonStatus, EnvPrecedence, Group, IoClass, LockStyle, RelaunchMode, SigpipeMode, StartState, StartupTimings, User};
+pub use watchdog::{heartbeat, WatchdogAction};
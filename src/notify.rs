@@ -0,0 +1,69 @@
+//! Readiness notification for supervisors other than Systemd.
+//!
+//! A launcher creates a pipe, exports the write end's file descriptor number in
+//! `READY_FD`, and blocks reading the other end. DaemonForge writes a single byte
+//! to that descriptor once the daemon setup has completed: after the privileged
+//! action, the privilege drop and the `on_ready` hook have all succeeded.
+
+use crate::error::DaemonResult;
+#[cfg(unix)]
+use crate::error::DaemonError;
+#[cfg(unix)]
+use std::fs::File;
+
+/// Environment variable holding the readiness file descriptor.
+pub const READY_FD_VAR: &str = "READY_FD";
+
+/// Notifies the launcher through the `READY_FD` descriptor, if one was provided.
+///
+/// Returns `Ok(true)` if a notification was sent and `Ok(false)` if `READY_FD` is not set.
+/// The variable is removed afterwards so child processes don't notify twice.
+/// DaemonForge calls this automatically during `start()`; it is exposed for
+/// daemons that manage their own startup sequence.
+///
+/// (Windows) Always returns `Ok(false)`.
+pub fn notify_ready_fd() -> DaemonResult<bool> {
+    #[cfg(unix)]
+    {
+        match take_ready_fd()? {
+            Some(file) => {
+                send_ready(file)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    #[cfg(not(unix))]
+    Ok(false)
+}
+
+/// Takes ownership of the `READY_FD` descriptor and clears the variable.
+#[cfg(unix)]
+pub(crate) fn take_ready_fd() -> DaemonResult<Option<File>> {
+    use std::os::unix::io::FromRawFd;
+
+    let Some(value) = std::env::var_os(READY_FD_VAR) else {
+        return Ok(None);
+    };
+    unsafe { std::env::remove_var(READY_FD_VAR) };
+
+    let fd = value
+        .to_str()
+        .and_then(|v| v.trim().parse::<libc::c_int>().ok())
+        .filter(|fd| *fd >= 0 && unsafe { libc::fcntl(*fd, libc::F_GETFD) } >= 0)
+        .ok_or_else(|| {
+            DaemonError::EnvError(format!("{} is not a valid file descriptor", READY_FD_VAR))
+        })?;
+
+    Ok(Some(unsafe { File::from_raw_fd(fd) }))
+}
+
+/// Writes the readiness byte and closes the descriptor.
+#[cfg(unix)]
+pub(crate) fn send_ready(mut file: File) -> DaemonResult<()> {
+    use std::io::Write;
+
+    file.write_all(b"\n").map_err(|e| DaemonError::io("write READY_FD", e))?;
+    Ok(())
+}
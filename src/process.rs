@@ -0,0 +1,289 @@
+//! Helpers for inspecting processes from outside the daemon (status, stop, tooling)
+//! and the state DaemonForge recorded for the daemon itself.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// PID written to the PID file by this process; `0` until one is written.
+static RECORDED_PID: AtomicU32 = AtomicU32::new(0);
+
+/// Absolute path of the PID file written by this process.
+static RECORDED_PID_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Directory this process was chrooted into by DaemonForge.
+static CHROOT_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Whether DaemonForge currently holds the instance lock for this process.
+static LOCK_HELD: AtomicBool = AtomicBool::new(false);
+
+/// Returns `true` if a process with the given PID is currently running.
+///
+/// - **Unix:** `kill(pid, 0)`. `EPERM` counts as alive (the process exists but belongs
+///   to another user). On Linux, zombies (exited but not yet reaped) count as dead.
+/// - **Windows:** `OpenProcess` + `GetExitCodeProcess`, alive while `STILL_ACTIVE`.
+///   Access denied counts as alive.
+///
+/// PID `0` is never considered alive.
+pub fn pid_is_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    return crate::sys::unix::pid_is_alive(pid);
+
+    #[cfg(windows)]
+    return crate::sys::windows::pid_is_alive(pid);
+}
+
+/// Returns the PID this process wrote to its PID file, or `None` if it wrote none
+/// (no PID file configured, or called before/outside the daemon setup).
+///
+/// Use it to log or register the daemon under the same identifier that external
+/// tools read from the PID file.
+pub fn recorded_pid() -> Option<u32> {
+    match RECORDED_PID.load(Ordering::Relaxed) {
+        0 => None,
+        pid => Some(pid),
+    }
+}
+
+/// Returns `true` if this process appears to run inside a container.
+///
+/// Conservative: only positive markers count, so an undetected container yields `false`.
+/// Checks, in order:
+/// - a non-empty `container` environment variable (Podman, systemd-nspawn, LXC);
+/// - **Unix:** `/.dockerenv` (Docker) or `/run/.containerenv` (Podman);
+/// - **Linux:** a `docker`, `kubepods`, `containerd`, `libpod` or `lxc` entry in
+///   `/proc/1/cgroup` (cgroup v1 hosts; cgroup v2 namespaces hide the path).
+pub fn in_container() -> bool {
+    if std::env::var_os("container").is_some_and(|v| !v.is_empty()) {
+        return true;
+    }
+
+    #[cfg(unix)]
+    if ["/.dockerenv", "/run/.containerenv"].iter().any(|marker| std::path::Path::new(marker).exists()) {
+        return true;
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Ok(cgroup) = std::fs::read_to_string("/proc/1/cgroup") {
+        const RUNTIMES: [&str; 5] = ["docker", "kubepods", "containerd", "libpod", "lxc"];
+        return cgroup.lines().any(|line| RUNTIMES.iter().any(|runtime| line.contains(runtime)));
+    }
+
+    false
+}
+
+/// Stores the PID just written to the PID file at `path`, for `recorded_pid()` and `health()`.
+pub(crate) fn set_recorded_pid(pid: u32, path: PathBuf) {
+    RECORDED_PID.store(pid, Ordering::Relaxed);
+    *RECORDED_PID_PATH.lock().unwrap_or_else(|e| e.into_inner()) = Some(path);
+}
+
+/// The PID file recorded by `set_recorded_pid`, and the PID written to it.
+#[cfg(windows)]
+pub(crate) fn recorded_pid_file() -> Option<(u32, PathBuf)> {
+    let path = RECORDED_PID_PATH.lock().unwrap_or_else(|e| e.into_inner()).clone()?;
+    recorded_pid().map(|pid| (pid, path))
+}
+
+/// Records the `chroot` directory for `health()`.
+#[cfg(unix)]
+pub(crate) fn set_chroot_dir(path: PathBuf) {
+    *CHROOT_DIR.lock().unwrap_or_else(|e| e.into_inner()) = Some(path);
+}
+
+/// Records whether the instance lock is held, for `health()`.
+pub(crate) fn set_lock_held(held: bool) {
+    LOCK_HELD.store(held, Ordering::Relaxed);
+}
+
+/// Returns the startup phase durations recorded for this process when
+/// `profile_startup(true)` is set, or `None` if profiling was not enabled.
+///
+/// Always `None` on Windows.
+pub fn startup_timings() -> Option<crate::StartupTimings> {
+    #[cfg(unix)]
+    return crate::sys::unix::startup_timings();
+
+    #[cfg(windows)]
+    return None;
+}
+
+/// Returns `true` once after each `SIGHUP` the daemon handled, and clears the request.
+///
+/// Set after the `on_reload` hook succeeded (and stdio was reopened, with
+/// `reopen_stdio_on_reload`), so the main loop can pick up the new configuration
+/// outside signal context. A hook may also just return `Ok(())` and leave the whole
+/// reload to the main loop. Several signals between two checks count as one request.
+///
+/// (Windows) Always returns `false`.
+pub fn take_reload_request() -> bool {
+    #[cfg(unix)]
+    return crate::sys::unix::take_reload_request();
+
+    #[cfg(windows)]
+    return false;
+}
+
+/// (Unix) Takes ownership of the daemon's PID file lock.
+///
+/// Once `start()` has returned in the daemon, DaemonForge keeps the locked PID file open
+/// for the lifetime of the process. Claiming the descriptor hands that lifetime to the caller: the lock is
+/// held while the `OwnedFd` lives and released when it is dropped. The PID file itself is
+/// not removed. Returns `None` if there is no lock, it was already taken, or it belongs
+/// to the `detect_pid_tampering` thread.
+#[cfg(unix)]
+pub fn take_lock_fd() -> Option<std::os::fd::OwnedFd> {
+    crate::sys::unix::take_lock_fd()
+}
+
+/// (Unix) Returns the read end of the pipe set up with `signal_pipe`, or `None` if none
+/// was configured. The descriptor is non-blocking and stays open for the life of the process.
+#[cfg(unix)]
+pub fn signal_pipe_fd() -> Option<std::os::unix::io::RawFd> {
+    crate::sys::unix::signal_pipe_fd()
+}
+
+/// (Unix) Switches the effective user ID back to root in a daemon started with
+/// `privilege_regainable(true)`. Pair every call with `drop_privileges()`.
+///
+/// Fails with `DaemonError::PrivilegeError` if privileges were dropped permanently
+/// (the default) or never dropped.
+#[cfg(unix)]
+pub fn regain_privileges() -> crate::DaemonResult<()> {
+    crate::sys::unix::regain_privileges()
+}
+
+/// (Unix) Switches the effective user ID back to the configured `user` after
+/// `regain_privileges()`.
+#[cfg(unix)]
+pub fn drop_privileges() -> crate::DaemonResult<()> {
+    crate::sys::unix::drop_privileges()
+}
+
+/// (Windows) Returns the handles the launcher passed with `inherit_handles`,
+/// in the order they were given. Empty if none were passed.
+#[cfg(windows)]
+pub fn inherited_handles() -> Vec<std::os::windows::io::RawHandle> {
+    crate::sys::windows::inherited_handles()
+}
+
+/// Snapshot of the process state DaemonForge established, returned by `health()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    /// Current process ID.
+    pub pid: u32,
+    /// (Unix) Effective user ID. `None` on Windows.
+    pub euid: Option<u32>,
+    /// (Unix) Effective group ID. `None` on Windows.
+    pub egid: Option<u32>,
+    /// Directory the daemon was chrooted into, if any.
+    pub chroot: Option<PathBuf>,
+    /// Current working directory (as seen inside the `chroot`).
+    pub working_directory: Option<PathBuf>,
+    /// Whether DaemonForge holds the instance lock. `false` once claimed with `take_lock_fd`.
+    pub lock_held: bool,
+    /// Absolute path of the PID file written by this process.
+    pub pid_file: Option<PathBuf>,
+    /// Whether the PID file still exists and contains this process's PID.
+    pub pid_file_matches: bool,
+    /// Whether stdin, stdout or stderr is still attached to a terminal.
+    pub attached_to_terminal: bool,
+}
+
+/// Reports the daemon's own runtime state, e.g. for an admin or audit endpoint
+/// ("running as uid 33, chrooted, lock held").
+///
+/// Read-only and cheap: it only re-reads the PID file.
+pub fn health() -> HealthReport {
+    use std::io::IsTerminal;
+
+    let pid_file = RECORDED_PID_PATH.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let pid_file_matches = pid_file.as_ref().is_some_and(|path| {
+        // An fcntl-locked PID file must be read through the lock's own descriptor
+        #[cfg(unix)]
+        let content = crate::sys::unix::read_fcntl_pid_file().unwrap_or_else(|| std::fs::read_to_string(path));
+        #[cfg(not(unix))]
+        let content = std::fs::read_to_string(path);
+        content.ok().and_then(|s| s.trim().parse::<u32>().ok()) == recorded_pid()
+    });
+
+    #[cfg(unix)]
+    let (euid, egid) = unsafe { (Some(libc::geteuid()), Some(libc::getegid())) };
+    #[cfg(not(unix))]
+    let (euid, egid) = (None, None);
+
+    HealthReport {
+        pid: std::process::id(),
+        euid,
+        egid,
+        chroot: CHROOT_DIR.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        working_directory: std::env::current_dir().ok(),
+        lock_held: LOCK_HELD.load(Ordering::Relaxed),
+        pid_file,
+        pid_file_matches,
+        attached_to_terminal: std::io::stdin().is_terminal()
+            || std::io::stdout().is_terminal()
+            || std::io::stderr().is_terminal(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    /// Spawns a short-lived copy of the test binary (`--list` prints and exits).
+    fn spawn_short_lived() -> std::process::Child {
+        Command::new(std::env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn own_process_is_alive() {
+        assert!(pid_is_alive(std::process::id()));
+    }
+
+    #[test]
+    fn invalid_pids_are_not_alive() {
+        // 0 (and, on Unix, values that wrap to negative) would address process groups
+        assert!(!pid_is_alive(0));
+        assert!(!pid_is_alive(u32::MAX));
+        assert!(!pid_is_alive(i32::MAX as u32 + 1));
+    }
+
+    #[test]
+    fn reaped_child_is_not_alive() {
+        let mut child = spawn_short_lived();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(!pid_is_alive(pid));
+    }
+
+    /// A process running as another user answers `kill(pid, 0)` with `EPERM`, which still
+    /// means it exists. Init always runs; as root the probe simply succeeds.
+    #[cfg(unix)]
+    #[test]
+    fn foreign_process_is_alive() {
+        assert!(pid_is_alive(1));
+    }
+
+    /// An exited but not yet reaped child still accepts signals, yet is no longer running.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn zombie_is_not_alive() {
+        let mut child = spawn_short_lived();
+        let pid = child.id();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while pid_is_alive(pid) {
+            assert!(std::time::Instant::now() < deadline, "child {} never reported dead", pid);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        // Still a zombie: the probe saw through it without reaping
+        assert!(std::fs::read_to_string(format!("/proc/{}/stat", pid)).is_ok());
+        child.wait().unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+//! Graceful shutdown backing `ForgeDaemon::on_shutdown`, `on_shutdown_fallible`,
+//! `is_shutdown_requested()` and `ShutdownGuard`.
+
+use crate::error::DaemonResult;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by the `SIGTERM`/`SIGINT` handler.
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// An `on_shutdown` or `on_shutdown_fallible` hook.
+pub(crate) type ShutdownHook = Box<dyn FnOnce() -> DaemonResult<()> + Send>;
+
+/// The `on_shutdown` hook, until a `ShutdownGuard` runs it.
+static SHUTDOWN_HOOK: Mutex<Option<ShutdownHook>> = Mutex::new(None);
+
+/// Returns `true` once `SIGTERM` or `SIGINT` was received by a daemon configured
+/// with `on_shutdown`. Cheap enough to check on every iteration of the main loop.
+///
+/// Always `false` without `on_shutdown`, and on Windows.
+pub fn is_shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Runs the `on_shutdown` hook outside the signal handler, on the thread holding it.
+///
+/// The hook runs at most once per process: on the first `poll()` after a shutdown was
+/// requested, or when the guard is dropped (the main loop returned or unwound),
+/// whichever comes first. If an `on_shutdown_fallible` hook fails, the process exits
+/// with the error's exit code instead of returning.
+///
+/// ```no_run
+/// let guard = daemon_forge::ShutdownGuard::new();
+/// while !guard.poll() {
+///     // ... serve ...
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct ShutdownGuard {
+    _private: (),
+}
+
+impl ShutdownGuard {
+    pub fn new() -> Self {
+        ShutdownGuard { _private: () }
+    }
+
+    /// Returns `true` if a shutdown was requested, running the hook the first time.
+    pub fn poll(&self) -> bool {
+        if !is_shutdown_requested() {
+            return false;
+        }
+        run_hook();
+        true
+    }
+}
+
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        run_hook();
+    }
+}
+
+fn run_hook() {
+    let hook = SHUTDOWN_HOOK.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(Err(e)) = hook.map(|hook| hook()) {
+        eprintln!("{}", crate::daemon::critical_line(&format!("Shutdown failed: {}", e)));
+        std::process::exit(e.exit_code());
+    }
+}
+
+/// Stores `hook` and installs the `SIGTERM`/`SIGINT` handler that flags the request.
+#[cfg(unix)]
+pub(crate) fn install(hook: ShutdownHook) -> DaemonResult<()> {
+    // Only an atomic store: the sole kind of work that is async-signal-safe here
+    extern "C" fn request_shutdown(_: libc::c_int) {
+        SHUTDOWN_REQUESTED.store(true, Ordering::Relaxed);
+    }
+
+    *SHUTDOWN_HOOK.lock().unwrap_or_else(|e| e.into_inner()) = Some(hook);
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = request_shutdown as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        for sig in [libc::SIGTERM, libc::SIGINT] {
+            if libc::sigaction(sig, &action, std::ptr::null_mut()) < 0 {
+                return Err(crate::error::DaemonError::io("sigaction", std::io::Error::last_os_error()));
+            }
+        }
+    }
+    Ok(())
+}
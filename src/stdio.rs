@@ -1,4 +1,10 @@
 use std::fs::File;
+use std::io;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
 
 /// Defines the behavior of input/output streams (stdin, stdout, stderr).
 #[derive(Debug)]
@@ -7,8 +13,22 @@ pub enum Stdio {
     Devnull,
     /// Redirects the stream to the specified file.
     RedirectToFile(File),
+    /// Redirects the stream to a file opened by DaemonForge at `start()`, inside the
+    /// process that will own it. Output streams append to the file (creating it if needed);
+    /// stdin opens it for reading.
+    RedirectToPath(PathBuf),
+    /// (Unix) Sends the stream over an already-connected TCP connection,
+    /// e.g. to a remote log collector.
+    ///
+    /// The caller establishes the connection; DaemonForge never reconnects, so output
+    /// is lost (and writes fail with `EPIPE`) once the peer goes away.
+    /// Not supported on Windows, where `start` fails with an IO error.
+    TcpStream(TcpStream),
     /// Keeps the original stream (useful for debugging, but not recommended for production).
     Keep,
+    /// Only valid for stderr: shares stdout's target, so both streams write through the
+    /// same open file in order. See `ForgeDaemon::merge_stdout_stderr`.
+    SameAsStdout,
 }
 
 impl Stdio {
@@ -16,6 +36,77 @@ impl Stdio {
     pub fn devnull() -> Self {
         Stdio::Devnull
     }
+
+    /// Creates a configuration that keeps the original stream.
+    pub fn keep() -> Self {
+        Stdio::Keep
+    }
+
+    /// Creates a configuration that redirects to `path`, opened when the daemon starts.
+    ///
+    /// Relative paths are resolved against the launcher's working directory.
+    /// On Unix, the file is opened with `O_NOFOLLOW` unless `logs_nofollow(false)` is set.
+    pub fn file<P: Into<PathBuf>>(path: P) -> Self {
+        Stdio::RedirectToPath(path.into())
+    }
+
+    /// Opens `path` now, in append mode (creating it if needed), and redirects to it.
+    ///
+    /// Previous content is kept, so restarts never erase the log history.
+    pub fn append<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Stdio::RedirectToFile(file))
+    }
+
+    /// Opens `path` now, truncating it (or creating it if needed), and redirects to it.
+    pub fn truncate<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = std::fs::OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
+        Ok(Stdio::RedirectToFile(file))
+    }
+
+    /// Creates a stderr configuration that writes wherever stdout goes.
+    pub fn merge() -> Self {
+        Stdio::SameAsStdout
+    }
+}
+
+impl Stdio {
+    /// Copies the configuration for `ForgeDaemon::clone_config`. Open files and streams
+    /// can't be shared between daemons and become `Devnull`.
+    pub(crate) fn clone_config(&self) -> Self {
+        match self {
+            Stdio::RedirectToPath(path) => Stdio::RedirectToPath(path.clone()),
+            Stdio::Keep => Stdio::Keep,
+            Stdio::SameAsStdout => Stdio::SameAsStdout,
+            Stdio::Devnull | Stdio::RedirectToFile(_) | Stdio::TcpStream(_) => Stdio::Devnull,
+        }
+    }
+
+    /// Returns the descriptor of the open file or stream the configuration owns, if any.
+    #[cfg(unix)]
+    pub(crate) fn raw_fd(&self) -> Option<std::os::unix::io::RawFd> {
+        use std::os::unix::io::AsRawFd;
+
+        match self {
+            Stdio::RedirectToFile(f) => Some(f.as_raw_fd()),
+            Stdio::TcpStream(s) => Some(s.as_raw_fd()),
+            _ => None,
+        }
+    }
+
+    /// Returns `false` if the stream redirects to a file that was not opened for writing.
+    pub(crate) fn is_writable(&self) -> bool {
+        match self {
+            #[cfg(unix)]
+            Stdio::RedirectToFile(f) => {
+                use std::os::unix::io::AsRawFd;
+
+                let flags = unsafe { libc::fcntl(f.as_raw_fd(), libc::F_GETFL) };
+                flags >= 0 && (flags & libc::O_ACCMODE) != libc::O_RDONLY
+            }
+            _ => true,
+        }
+    }
 }
 
 impl From<File> for Stdio {
@@ -23,3 +114,105 @@ impl From<File> for Stdio {
         Stdio::RedirectToFile(f)
     }
 }
+
+impl From<TcpStream> for Stdio {
+    fn from(s: TcpStream) -> Self {
+        Stdio::TcpStream(s)
+    }
+}
+
+/// Current paths of stdout/stderr redirected with `Stdio::file`, for `reopen_stdio_on_reload`.
+static STDIO_PATHS: Mutex<[Option<PathBuf>; 2]> = Mutex::new([None, None]);
+
+/// Changes the files stdout and stderr are reopened to on the next `SIGHUP`
+/// (`reopen_stdio_on_reload`). `None` keeps the current path.
+///
+/// Call it from the `on_reload` hook when the new configuration moves the logs.
+/// Only affects streams configured with `Stdio::file`.
+pub fn update_stdio_paths(stdout: Option<PathBuf>, stderr: Option<PathBuf>) {
+    let mut paths = STDIO_PATHS.lock().unwrap_or_else(|e| e.into_inner());
+    for (slot, path) in paths.iter_mut().zip([stdout, stderr]) {
+        if slot.is_some() && path.is_some() {
+            *slot = path;
+        }
+    }
+}
+
+/// Remembers the absolute path stdout (`index` 0) or stderr (1) was redirected to.
+pub(crate) fn record_stdio_path(index: usize, path: &Path) {
+    let path = std::env::current_dir().map(|cwd| cwd.join(path)).unwrap_or_else(|_| path.to_path_buf());
+    STDIO_PATHS.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(path);
+}
+
+/// Set when stderr was pointed at stdout (`Stdio::SameAsStdout`), so reopening stdout
+/// re-merges stderr as well.
+#[cfg(unix)]
+static STDERR_MERGED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+pub(crate) fn set_stderr_merged() {
+    STDERR_MERGED.store(true, Ordering::Relaxed);
+}
+
+#[cfg(unix)]
+pub(crate) fn stderr_merged() -> bool {
+    STDERR_MERGED.load(Ordering::Relaxed)
+}
+
+/// Current stdout/stderr paths recorded by `record_stdio_path`.
+pub(crate) fn stdio_paths() -> [Option<PathBuf>; 2] {
+    STDIO_PATHS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Opens a `Stdio::RedirectToPath` target: for reading (`stdin`) or appending.
+pub(crate) fn open_path(path: &Path, read: bool, nofollow: bool) -> io::Result<File> {
+    let mut options = std::fs::OpenOptions::new();
+    if read {
+        options.read(true);
+    } else {
+        options.append(true).create(true);
+    }
+
+    #[cfg(unix)]
+    if nofollow {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+    #[cfg(not(unix))]
+    let _ = nofollow;
+
+    options.open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clone_config_keeps_paths_and_drops_open_files() {
+        let path = PathBuf::from("logs/out.log");
+        assert!(matches!(Stdio::file(&path).clone_config(), Stdio::RedirectToPath(p) if p == path));
+        assert!(matches!(Stdio::keep().clone_config(), Stdio::Keep));
+        assert!(matches!(Stdio::merge().clone_config(), Stdio::SameAsStdout));
+        assert!(matches!(Stdio::devnull().clone_config(), Stdio::Devnull));
+
+        let file = File::open(std::env::current_exe().unwrap()).unwrap();
+        assert!(matches!(Stdio::from(file).clone_config(), Stdio::Devnull));
+    }
+
+    #[test]
+    fn update_stdio_paths_only_moves_recorded_streams() {
+        let out = std::env::temp_dir().join("daemon_forge_out.log");
+        record_stdio_path(0, &out);
+        assert_eq!(stdio_paths(), [Some(out.clone()), None]);
+
+        // `None` keeps the current path
+        update_stdio_paths(None, None);
+        assert_eq!(stdio_paths(), [Some(out), None]);
+
+        // stderr isn't a `Stdio::file` stream here, so it has nothing to reopen
+        let moved = std::env::temp_dir().join("daemon_forge_moved.log");
+        update_stdio_paths(Some(moved.clone()), Some(PathBuf::from("/elsewhere/err.log")));
+        assert_eq!(stdio_paths(), [Some(moved), None]);
+    }
+}
@@ -1,238 +1,2162 @@
 use crate::daemon::ForgeDaemon;
 use crate::error::{DaemonError, DaemonResult};
 use crate::stdio::Stdio;
-use crate::types::{Group, User};
+use crate::types::{Group, LockStyle, RelaunchMode, SigpipeMode, StartState, StartupTimings, User};
+#[cfg(target_os = "linux")]
+use crate::types::IoClass;
 use std::ffi::CString;
+use std::fs::File;
 use std::io;
-use std::path::Path;
+use std::os::fd::OwnedFd;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "linux")]
+use sd_notify::NotifyState;
+
+/// Evaluates a libc call that returns `-1` on failure, retrying it while it fails with
+/// `EINTR`: a signal arriving mid-call (e.g. `SIGCHLD`) must not abort the setup.
+macro_rules! retry_eintr {
+    ($call:expr) => {
+        loop {
+            let ret = $call;
+            if ret != -1 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                break ret;
+            }
+        }
+    };
+}
+
+/// Main entry point for Unix systems.
+/// 
+/// It automatically detects if the process is being managed by Systemd (via `NOTIFY_SOCKET`).
+/// - **Systemd Detected:** Runs in the foreground, notifies `READY=1`, and executes the payload.
+/// - **Manual Start:** Performs the classic double-fork machination to daemonize into the background,
+///   or re-spawns the executable with `RelaunchMode::Respawn`.
+///
+/// The launching process exits once the daemon is detached, unless `exit_launcher` is
+/// false (`try_start`): it then returns `StartState::Launcher`.
+pub fn start<T>(mut daemon: ForgeDaemon<T>, exit_launcher: bool) -> DaemonResult<StartState<T>> {
+    if let Some(lock_fd) = std::env::var_os(REEXEC_VAR) {
+        return start_reexecuted(daemon, lock_fd).map(|setup| StartState::Daemon { setup });
+    }
+    if daemon.relaunch_mode == RelaunchMode::Respawn && std::env::var_os(daemon.internal_flag()?).is_some() {
+        return start_respawned_child(daemon).map(|setup| StartState::Daemon { setup });
+    }
+
+    daemon.check_required_env()?;
+    if daemon.pid_dir_create {
+        create_pid_dir(&daemon)?;
+    }
+    if daemon.create_log_dirs {
+        create_log_dirs(&daemon)?;
+    }
+    if let Some(hook) = daemon.before_fork.take() {
+        hook()?;
+    }
+    if !daemon.preserved_fds.is_empty() {
+        clear_cloexec(&daemon.preserved_fds)?;
+    }
+    if daemon.block_signals_during_setup {
+        block_setup_signals()?;
+    }
+
+    let result = start_mode(daemon, exit_launcher);
+    // No-op unless setup failed before the action ran
+    restore_setup_signals();
+    result
+}
+
+fn start_mode<T>(daemon: ForgeDaemon<T>, exit_launcher: bool) -> DaemonResult<StartState<T>> {
+    #[cfg(target_os = "linux")]
+    {
+        // If NOTIFY_SOCKET is present, Systemd expects us to stay in the foreground
+        if std::env::var("NOTIFY_SOCKET").is_ok() {
+            return start_systemd_mode(daemon).map(|setup| StartState::Daemon { setup });
+        }
+    }
+
+    // The container runtime supervises us directly: stay in the foreground
+    if daemon.container_aware && crate::process::in_container() {
+        apply_io_redirection(&daemon)?;
+        return execute_daemon_logic(daemon, None).map(|setup| StartState::Daemon { setup });
+    }
+
+    match daemon.relaunch_mode {
+        RelaunchMode::Fork => start_background_mode(daemon, exit_launcher),
+        RelaunchMode::Respawn => respawn_daemon(daemon, exit_launcher),
+    }
+}
+
+/// Launcher side of `RelaunchMode::Respawn`: spawns a fresh copy of the executable
+/// flagged as the daemon, then exits. No `fork` happens in this process.
+fn respawn_daemon<T>(daemon: ForgeDaemon<T>, exit_launcher: bool) -> DaemonResult<StartState<T>> {
+    let exe_path = std::env::current_exe().map_err(|e| DaemonError::io("current_exe", e))?;
+    daemon.check_exe_hash(&exe_path)?;
+    let mut cmd = std::process::Command::new(exe_path);
+    match &daemon.child_args {
+        Some(args) => cmd.args(args),
+        None => cmd.args(std::env::args_os().skip(1)),
+    };
+    // The child applies its own stdio, environment and setup from the same builder
+    cmd.env(daemon.internal_flag()?, "1");
+
+    // The daemon writes a byte once setup succeeded; EOF without it means failure.
+    let status = if daemon.launcher_exit_on_failure {
+        use std::os::unix::io::AsRawFd;
+
+        let (reader, writer) = io::pipe().map_err(|e| DaemonError::io("pipe", e))?;
+        let fd = writer.as_raw_fd();
+        // Unlike the fork path, the write end must survive `exec`
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, 0) } < 0 {
+            return Err(DaemonError::io("fcntl", io::Error::last_os_error()));
+        }
+        cmd.env(STATUS_FD_VAR, fd.to_string());
+        Some((reader, writer))
+    } else {
+        None
+    };
+
+    // The signal mask is inherited: hand the child the original one
+    restore_setup_signals();
+    let child = cmd.spawn().map_err(|e| DaemonError::io("spawn", e))?;
+    let set_up = match status {
+        Some((reader, writer)) => {
+            // Release our copy of the write end so a dead child yields EOF
+            drop(writer);
+            wait_for_status(reader)
+        }
+        None => true,
+    };
+    launcher_done(exit_launcher, set_up, || Ok(child.id()))
+}
+
+/// Set by the launcher in `RelaunchMode::Respawn` with `launcher_exit_on_child_failure`:
+/// the descriptor of the status pipe's write end.
+const STATUS_FD_VAR: &str = "__DAEMON_FORGE_STATUS_FD";
+
+/// Takes the status pipe passed in `STATUS_FD_VAR`, if any, and clears the variable.
+fn take_status_fd() -> Option<io::PipeWriter> {
+    use std::os::unix::io::{FromRawFd, OwnedFd};
+
+    let value = std::env::var_os(STATUS_FD_VAR)?;
+    unsafe { std::env::remove_var(STATUS_FD_VAR) };
+    let fd = value
+        .to_str()
+        .and_then(|v| v.parse::<libc::c_int>().ok())
+        .filter(|fd| *fd > libc::STDERR_FILENO && unsafe { libc::fcntl(*fd, libc::F_GETFD) } >= 0)?;
+    // Not passed on to programs the daemon executes
+    unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    Some(io::PipeWriter::from(unsafe { OwnedFd::from_raw_fd(fd) }))
+}
+
+/// Daemon side of `RelaunchMode::Respawn`: detaches and runs the setup.
+fn start_respawned_child<T>(daemon: ForgeDaemon<T>) -> DaemonResult<T> {
+    unsafe { std::env::remove_var(daemon.internal_flag()?) };
+    // Dropped on any failure before the setup reports success, which the launcher sees as EOF
+    let status = take_status_fd();
+
+    if daemon.block_signals_during_setup {
+        block_setup_signals()?;
+    }
+
+    let result = detach_respawned_child(daemon, status);
+    restore_setup_signals();
+    result
+}
+
+fn detach_respawned_child<T>(daemon: ForgeDaemon<T>, status: Option<io::PipeWriter>) -> DaemonResult<T> {
+    let tolerated = new_session(daemon.require_setsid)?;
+    apply_io_redirection(&daemon)?;
+    warn_session_kept(tolerated);
+    execute_daemon_logic(daemon, status)
+}
+
+/// Starts a new session with `setsid`. Under `require_setsid(false)` an `EPERM` failure
+/// is returned instead, to be reported with `warn_session_kept` once stderr is redirected.
+fn new_session(require: bool) -> DaemonResult<Option<io::Error>> {
+    if unsafe { libc::setsid() } >= 0 {
+        return Ok(None);
+    }
+    let err = io::Error::last_os_error();
+    if !require && err.raw_os_error() == Some(libc::EPERM) {
+        return Ok(Some(err));
+    }
+    Err(DaemonError::SyscallError {
+        call: "setsid",
+        errno: err.raw_os_error().unwrap_or(0),
+    })
+}
+
+fn warn_session_kept(tolerated: Option<io::Error>) {
+    if let Some(err) = tolerated {
+        eprintln!("[DaemonForge Warning] setsid failed ({}); continuing in the current session", err);
+    }
+}
+
+
+/// Set by `reexec_on_upgrade` before `execv`: the inherited lock descriptor, or empty.
+const REEXEC_VAR: &str = "__DAEMON_FORGE_REEXEC";
+
+/// Inherited lock descriptor `acquire_lock` adopts after an upgrade; `-1` if none.
+static INHERITED_LOCK_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// The process is already the detached daemon: redo the setup without forking.
+fn start_reexecuted<T>(daemon: ForgeDaemon<T>, lock_fd: std::ffi::OsString) -> DaemonResult<T> {
+    unsafe { std::env::remove_var(REEXEC_VAR) };
+    if let Some(fd) = lock_fd.to_str().and_then(|fd| fd.parse::<libc::c_int>().ok()) {
+        INHERITED_LOCK_FD.store(fd, Ordering::Relaxed);
+    }
+    apply_io_redirection(&daemon)?;
+    execute_daemon_logic(daemon, None)
+}
+
+/// Identity of the executable, compared by `reexec_on_upgrade`.
+fn exe_identity(path: &Path) -> Option<(u64, u64, u64, i64, i64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.dev(), meta.ino(), meta.size(), meta.mtime(), meta.mtime_nsec()))
+}
+
+/// Starts the thread that re-executes the daemon on `signal` once its binary changed.
+fn spawn_upgrade_watcher(signal: libc::c_int) -> DaemonResult<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let exe = std::env::current_exe().map_err(|e| DaemonError::io("current_exe", e))?;
+    let started_from = exe_identity(&exe);
+    let exe_c = CString::new(exe.as_os_str().as_bytes())
+        .map_err(|_| DaemonError::ConfigError(format!("Invalid executable path: {:?}", exe)))?;
+    let args: Vec<CString> = std::env::args_os()
+        .filter_map(|arg| CString::new(arg.as_bytes()).ok())
+        .collect();
+
+    let mut signals = signal_hook::iterator::Signals::new([signal])
+        .map_err(|e| DaemonError::io("register upgrade signal", e))?;
+
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            if exe_identity(&exe) == started_from {
+                eprintln!("[DaemonForge] Upgrade requested but {:?} is unchanged", exe);
+                continue;
+            }
+            eprintln!("[DaemonForge] {:?} changed; re-executing", exe);
+            let err = unsafe { reexec(&exe_c, &args) };
+            eprintln!("[DaemonForge] Re-exec failed: {}", err);
+        }
+    });
+    Ok(())
+}
+
+/// Hands the lock to the next image and replaces the process. Only returns on failure.
+unsafe fn reexec(exe: &CString, args: &[CString]) -> io::Error {
+    use std::io::Write;
+
+    let lock_fd = PERSISTED_LOCK_FD.load(Ordering::Relaxed);
+    if lock_fd >= 0 {
+        let flags = unsafe { libc::fcntl(lock_fd, libc::F_GETFD) };
+        if flags >= 0 {
+            unsafe { libc::fcntl(lock_fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) };
+        }
+    }
+    let inherited = if lock_fd >= 0 { lock_fd.to_string() } else { String::new() };
+    unsafe { std::env::set_var(REEXEC_VAR, inherited) };
+
+    let _ = io::stdout().flush();
+    let mut argv: Vec<*const libc::c_char> = args.iter().map(|arg| arg.as_ptr()).collect();
+    argv.push(std::ptr::null());
+    unsafe { libc::execv(exe.as_ptr(), argv.as_ptr()) };
+
+    let err = io::Error::last_os_error();
+    unsafe { std::env::remove_var(REEXEC_VAR) };
+    err
+}
+
+#[cfg(target_os = "linux")]
+fn start_systemd_mode<T>(daemon: ForgeDaemon<T>) -> DaemonResult<T> {
+
+    apply_io_redirection(&daemon)?;
+
+    // Notify Systemd that the service is ready.
+    // 'true' tells the library to unset the env var so it doesn't leak to children.
+    let _ = sd_notify::notify(true, &[NotifyState::Ready]);
+
+    execute_daemon_logic(daemon, None)
+}
+
+/// Runs the full daemon setup in the current process without forking,
+/// then executes `f` with the privileged action's output.
+///
+/// The PID file is removed and its lock released once `f` returns.
+pub fn foreground_run<T, R, F>(mut daemon: ForgeDaemon<T>, f: F) -> DaemonResult<R>
+where
+    F: FnOnce(T) -> DaemonResult<R>,
+{
+    // The foreground lock is never handed over: a re-executed image simply relocks
+    unsafe { std::env::remove_var(REEXEC_VAR) };
+    daemon.check_required_env()?;
+    if daemon.pid_dir_create {
+        create_pid_dir(&daemon)?;
+    }
+    if daemon.create_log_dirs {
+        create_log_dirs(&daemon)?;
+    }
+    if let Some(hook) = daemon.before_fork.take() {
+        hook()?;
+    }
+    apply_io_redirection(&daemon)?;
+
+    let die_with_parent = daemon.die_with_parent;
+    let parent = unsafe { libc::getppid() };
+    if die_with_parent {
+        watch_parent(parent)?;
+    }
+
+    if daemon.block_signals_during_setup {
+        block_setup_signals()?;
+    }
+
+    let setup = setup_daemon(daemon, None);
+    restore_setup_signals();
+    let (setup, lock) = setup?;
+
+    // Changing credentials clears the parent-death signal
+    #[cfg(target_os = "linux")]
+    if die_with_parent {
+        watch_parent(parent)?;
+    }
+
+    let result = f(setup);
+
+    if let Some(lock) = lock {
+        lock.release();
+    }
+    result
+}
+
+/// Double-Fork to detach from terminal and run in background.
+fn start_background_mode<T>(daemon: ForgeDaemon<T>, exit_launcher: bool) -> DaemonResult<StartState<T>> {
+    #[cfg(all(debug_assertions, target_os = "linux"))]
+    warn_if_multithreaded();
+
+    #[cfg(feature = "pty")]
+    if daemon.pty {
+        return start_pty_mode(daemon, exit_launcher);
+    }
+
+    // The child closes its end once stdio is redirected, waking the launcher.
+    let linger = match daemon.launcher_linger {
+        Some(timeout) => Some((timeout, io::pipe().map_err(|e| DaemonError::io("pipe", e))?)),
+        None => None,
+    };
+    // The daemon writes a byte once setup succeeded; EOF without it means failure.
+    let (status_reader, status_writer) = if daemon.launcher_exit_on_failure {
+        let (reader, writer) = io::pipe().map_err(|e| DaemonError::io("pipe", e))?;
+        (Some(reader), Some(writer))
+    } else {
+        (None, None)
+    };
+
+    // The intermediate child reports the daemon's PID to a launcher that returns
+    let pid_pipe = match exit_launcher {
+        true => None,
+        false => Some(io::pipe().map_err(|e| DaemonError::io("pipe", e))?),
+    };
+
+    let profile = daemon.profile_startup;
+
+    unsafe {
+        // Fork 1
+        let t = Instant::now();
+        if perform_fork()? > 0 {
+            restore_setup_signals();
+            if let Some((timeout, (reader, writer))) = linger {
+                drop(writer);
+                wait_for_hangup(&reader, timeout);
+            }
+            let set_up = match status_reader {
+                Some(reader) => {
+                    drop(status_writer);
+                    wait_for_status(reader)
+                }
+                None => true,
+            };
+            return launcher_done(exit_launcher, set_up, || read_daemon_pid(pid_pipe));
+        }
+        drop(status_reader);
+        let pid_writer = pid_pipe.map(|(_, writer)| writer);
+        record_phase(profile, t, Phase::Fork);
+
+        // New Session
+        let t = Instant::now();
+        let tolerated = new_session(daemon.require_setsid)?;
+        record_phase(profile, t, Phase::Setsid);
+
+        // IO Redirection
+        apply_io_redirection(&daemon)?;
+        warn_session_kept(tolerated);
+        drop(linger);
+
+        // Fork 2
+        let t = Instant::now();
+        let daemon_pid = perform_fork()?;
+        if daemon_pid > 0 {
+            report_daemon_pid(pid_writer, daemon_pid);
+            exit(0);
+        }
+        drop(pid_writer);
+        record_phase(profile, t, Phase::Fork);
+
+        // Execute the main daemon logic in the grandchild process
+        execute_daemon_logic(daemon, status_writer).map(|setup| StartState::Daemon { setup })
+    }
+}
+
+/// Ends the launcher side: exits with `set_up`'s status, or returns `StartState::Launcher`
+/// with the PID from `child_pid` when not `exit_launcher`.
+fn launcher_done<T>(
+    exit_launcher: bool,
+    set_up: bool,
+    child_pid: impl FnOnce() -> DaemonResult<u32>,
+) -> DaemonResult<StartState<T>> {
+    if exit_launcher {
+        exit(if set_up { 0 } else { 1 });
+    }
+    if !set_up {
+        return Err(DaemonError::io(
+            "daemon setup",
+            io::Error::other("the daemon failed during setup; see its stderr for details"),
+        ));
+    }
+    Ok(StartState::Launcher { child_pid: child_pid()? })
+}
+
+/// Reads the daemon's PID sent by the intermediate child with `report_daemon_pid`.
+fn read_daemon_pid(pid_pipe: Option<(io::PipeReader, io::PipeWriter)>) -> DaemonResult<u32> {
+    use std::io::Read;
+
+    let Some((mut reader, writer)) = pid_pipe else {
+        unreachable!("the PID pipe is created whenever the launcher returns");
+    };
+    drop(writer);
+    let mut pid = [0u8; 4];
+    reader.read_exact(&mut pid).map_err(|e| DaemonError::io("read daemon PID", e))?;
+    Ok(u32::from_ne_bytes(pid))
+}
+
+/// Intermediate child: passes the daemon's PID to the launcher, if it returns (`try_start`).
+fn report_daemon_pid(writer: Option<io::PipeWriter>, pid: libc::pid_t) {
+    use std::io::Write;
+
+    if let Some(mut writer) = writer {
+        let _ = writer.write_all(&(pid as u32).to_ne_bytes());
+    }
+}
+
+/// Double-fork variant of `start_background_mode` with a pseudo-terminal as the daemon's stdio.
+/// The launcher relays the master side until the daemon closes the slave.
+#[cfg(feature = "pty")]
+fn start_pty_mode<T>(daemon: ForgeDaemon<T>, exit_launcher: bool) -> DaemonResult<StartState<T>> {
+    let mut master: libc::c_int = -1;
+    let mut slave: libc::c_int = -1;
+    let pid_pipe = match exit_launcher {
+        true => None,
+        false => Some(io::pipe().map_err(|e| DaemonError::io("pipe", e))?),
+    };
+
+    unsafe {
+        if libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null(), std::ptr::null()) < 0 {
+            return Err(DaemonError::io("openpty", io::Error::last_os_error()));
+        }
+
+        // Fork 1
+        if perform_fork()? > 0 {
+            restore_setup_signals();
+            libc::close(slave);
+            relay_pty(master);
+            libc::close(master);
+            return launcher_done(exit_launcher, true, || read_daemon_pid(pid_pipe));
+        }
+        libc::close(master);
+        let pid_writer = pid_pipe.map(|(_, writer)| writer);
+
+        // New Session (the pty is deliberately not made the controlling terminal)
+        let tolerated = new_session(daemon.require_setsid)?;
+
+        for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+            if retry_eintr!(libc::dup2(slave, fd)) < 0 {
+                return Err(DaemonError::io("dup2", io::Error::last_os_error()));
+            }
+        }
+        libc::close(slave);
+        warn_session_kept(tolerated);
+
+        // Fork 2
+        let daemon_pid = perform_fork()?;
+        if daemon_pid > 0 {
+            report_daemon_pid(pid_writer, daemon_pid);
+            exit(0);
+        }
+        drop(pid_writer);
+
+        execute_daemon_logic(daemon, None).map(|setup| StartState::Daemon { setup })
+    }
+}
+
+/// Copies the pty master to the launcher's stdout and its stdin to the master,
+/// until the slave side is closed (read returns EOF or `EIO`).
+#[cfg(feature = "pty")]
+unsafe fn relay_pty(master: libc::c_int) {
+    let mut buf = [0u8; 4096];
+    let mut fds = [
+        libc::pollfd { fd: master, events: libc::POLLIN, revents: 0 },
+        libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 },
+    ];
+
+    loop {
+        if unsafe { retry_eintr!(libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1)) } < 0 {
+            return;
+        }
+
+        if fds[0].revents != 0 {
+            let n = unsafe { retry_eintr!(libc::read(master, buf.as_mut_ptr().cast(), buf.len())) };
+            if n <= 0 {
+                return;
+            }
+            unsafe { retry_eintr!(libc::write(libc::STDOUT_FILENO, buf.as_ptr().cast(), n as usize)) };
+        }
+
+        if fds[1].revents != 0 {
+            let n = unsafe { retry_eintr!(libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len())) };
+            if n <= 0 {
+                // Stdin closed: keep relaying output only
+                fds[1].fd = -1;
+            } else {
+                unsafe { retry_eintr!(libc::write(master, buf.as_ptr().cast(), n as usize)) };
+            }
+        }
+    }
+}
+
+/// The core execution logic common to both Systemd and Background modes.
+/// The PID file lock is kept for the lifetime of the process.
+fn execute_daemon_logic<T>(daemon: ForgeDaemon<T>, status: Option<io::PipeWriter>) -> DaemonResult<T> {
+    let (result, lock) = setup_daemon(daemon, status)?;
+    if let Some(lock) = lock {
+        lock.persist();
+    }
+    Ok(result)
+}
+
+/// Handles environment, chroot, PID files, privileges, and the user action.
+/// Returns the action output together with the held PID file lock, if any.
+///
+/// `status` is the launcher's status pipe (`launcher_exit_on_child_failure`), signalled
+/// once every step up to and including `on_ready` has succeeded.
+fn setup_daemon<T>(mut daemon: ForgeDaemon<T>, status: Option<io::PipeWriter>) -> DaemonResult<(T, Option<PidLock>)> {
+    let on_failure = daemon.on_setup_failure.take();
+    let mut lock = None;
+    match setup_steps(&mut daemon, status, &mut lock) {
+        Ok(result) => Ok((result, lock)),
+        Err(e) => {
+            // Stdio is redirected by now, so this lands in the daemon's log
+            daemon.log_error(&format!("Daemon setup failed. {}", e));
+            // Still holding the lock, so the hook can't race a new instance
+            crate::daemon::run_setup_failure_hook(on_failure, &e);
+            Err(e)
+        }
+    }
+}
+
+/// The steps of `setup_daemon`. The PID file lock is stored in `lock` as soon as it is taken.
+fn setup_steps<T>(daemon: &mut ForgeDaemon<T>, status: Option<io::PipeWriter>, lock: &mut Option<PidLock>) -> DaemonResult<T> {
+    // Captured before the environment is modified
+    let ready_fd = crate::notify::take_ready_fd()?;
+    let lock_path = daemon.lock_path();
+    let profile = daemon.profile_startup;
+
+    // Descriptors DaemonForge passes on or still owns, for `close_fds` and `assert_clean_fds`
+    let expected = {
+        use std::os::unix::io::AsRawFd;
+
+        let mut fds = vec![libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO];
+        fds.extend(ready_fd.as_ref().map(|f| f.as_raw_fd()));
+        fds.extend(status.as_ref().map(|w| w.as_raw_fd()));
+        fds.push(INHERITED_LOCK_FD.load(Ordering::Relaxed));
+        fds.extend_from_slice(&daemon.preserved_fds);
+        // Already dup2'd over 0/1/2, but closed again when the configuration is dropped
+        fds.extend([&daemon.stdin, &daemon.stdout, &daemon.stderr].into_iter().filter_map(|s| s.raw_fd()));
+        #[cfg(target_os = "linux")]
+        {
+            fds.extend(socket_activation_fds());
+            fds.extend(crate::activation::taken_fds());
+        }
+        fds
+    };
+    if daemon.close_fds {
+        close_inherited_fds(&expected);
+    }
+    #[cfg(target_os = "linux")]
+    if daemon.assert_clean_fds {
+        check_clean_fds(&expected)?;
+    }
+
+    unsafe {
+        // --- Environment Management ---
+        if daemon.clear_env {
+            #[cfg(target_os = "linux")]
+            libc::clearenv();
+        }
+        for (k, v) in &daemon.env_vars {
+            std::env::set_var(k, v);
+        }
+        daemon.dump_env()?;
+
+        // --- OOM Killer (before chroot: the jail may lack /proc) ---
+        #[cfg(target_os = "linux")]
+        if let Some(adj) = daemon.oom_score_adj {
+            set_oom_score_adj(adj)?;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(class) = daemon.ionice {
+            set_io_priority(class)?;
+        }
+
+        // --- System Configuration ---
+        if let Some(mask) = daemon.umask {
+            libc::umask(mask as libc::mode_t);
+        }
+
+        let t = Instant::now();
+        let cwd = CString::new(daemon.directory.to_str().unwrap()).map_err(|_| {
+            DaemonError::io("chdir", io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid CWD path",
+            ))
+        })?;
+        if libc::chdir(cwd.as_ptr()) < 0 {
+            return Err(DaemonError::io("chdir", io::Error::last_os_error()));
+        }
+        record_phase(profile, t, Phase::Chdir);
+
+        // --- Chroot Logic ---
+        if let Some(root) = &daemon.root {
+            let t = Instant::now();
+            let root_c = CString::new(root.to_str().unwrap()).map_err(|_| {
+                DaemonError::io("chroot", io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Invalid chroot path",
+                ))
+            })?;
+            if libc::chroot(root_c.as_ptr()) < 0 {
+                return Err(DaemonError::PrivilegeError(format!(
+                    "chroot failed: {}",
+                    io::Error::last_os_error()
+                )));
+            }
+            // Change dir to "/" after chroot unless the user manages it
+            if daemon.chroot_chdir && libc::chdir(c"/".as_ptr()) < 0 {
+                return Err(DaemonError::io("chdir", io::Error::last_os_error()));
+            }
+            crate::process::set_chroot_dir(root.clone());
+            record_phase(profile, t, Phase::Chroot);
+        }
+
+        // --- Locking & PID File Logic ---
+        if lock_path.is_some() || daemon.lock_file.is_some() || !daemon.additional_pid_files.is_empty() {
+            let t = Instant::now();
+            *lock = Some(write_pid_file_unix(lock_path.as_deref(), daemon)?);
+            if let (true, Some(path)) = (daemon.chown_pid, &lock_path) {
+                apply_chown(path, &daemon.user, &daemon.group)?;
+            }
+            record_phase(profile, t, Phase::PidFile);
+        }
+        if let (Some(interval), Some(path)) = (daemon.pid_heartbeat, lock.as_ref().and_then(|l| l.path.clone())) {
+            spawn_pid_heartbeat(path, interval);
+        }
+
+        // --- Runtime Directory ---
+        if let Some(dir) = daemon.runtime_dir.as_ref().filter(|dir| !dir.exists()) {
+            let mode = if daemon.runtime_dir_setgid { 0o2750 } else { 0o750 };
+            create_owned_dir(dir, mode, &daemon.user, &daemon.group)?;
+        }
+
+        // --- Temp Directory ---
+        if let Some(dir) = &daemon.tmp_dir {
+            // An existing directory may be shared (`/tmp`): never re-own or chmod it
+            if !dir.exists() {
+                create_owned_dir(dir, 0o700, &daemon.user, &daemon.group)?;
+            } else if !is_writable_by(dir, &daemon.user, &daemon.group) {
+                return Err(DaemonError::ConfigError(format!(
+                    "Temp directory {:?} is not writable by the daemon's user",
+                    dir
+                )));
+            }
+            for var in ["TMPDIR", "TMP", "TEMP"] {
+                std::env::set_var(var, dir);
+            }
+        }
+
+        // --- Core Dumps ---
+        if let Some(dir) = &daemon.core_dump_dir {
+            enable_core_dumps(dir)?;
+        }
+
+        // --- Binary Upgrades ---
+        if let Some(signal) = daemon.reexec_on_upgrade {
+            if daemon.root.is_some() {
+                return Err(DaemonError::ConfigError(
+                    "reexec_on_upgrade can't be combined with chroot".to_owned(),
+                ));
+            }
+            spawn_upgrade_watcher(signal)?;
+        }
+
+        // --- Diagnostics ---
+        if let Some(signal) = daemon.debug_signal {
+            spawn_debug_dumper(signal, format!("{:#?}", daemon))?;
+        }
+
+        // --- Signal Pipe ---
+        if !daemon.signal_pipe.is_empty() {
+            install_signal_pipe(&daemon.signal_pipe)?;
+        }
+
+        // --- Shutdown Handler (SIGTERM/SIGINT) ---
+        // Installed before `restore_setup_signals` unblocks them, so none is lost
+        if let Some(hook) = daemon.on_shutdown.take() {
+            crate::shutdown::install(hook)?;
+        }
+
+        // --- Reload Handler (SIGHUP) ---
+        let reopen = daemon.reopen_stdio_on_reload.then_some(daemon.logs_nofollow);
+        if daemon.on_reload.is_some() || reopen.is_some() {
+            spawn_reload_dispatcher(daemon.on_reload.take(), reopen)?;
+        }
+
+        set_sigpipe(daemon.sigpipe)?;
+
+        // Installed first: the syslog hook chains onto it
+        if daemon.capture_backtrace {
+            install_backtrace_panic_hook();
+        }
+        if daemon.panic_to_syslog {
+            install_syslog_panic_hook(daemon.name.as_deref());
+        }
+
+        // --- Hang Watchdog ---
+        if let Some((timeout, action)) = daemon.hang_watchdog.take() {
+            crate::watchdog::spawn(timeout, action);
+        }
+
+        // --- Privileged Action (Payload) ---
+        // This is where the user's loop runs, so pending stop signals are delivered now
+        restore_setup_signals();
+        let action = daemon.privileged_action.take().unwrap();
+        let t = Instant::now();
+        let result = with_umask(daemon.socket_umask, action)?;
+        record_phase(profile, t, Phase::PrivilegedAction);
+
+        // --- Drop Privileges ---
+        // (Only executed if the action returns, usually cleanup)
+        let t = Instant::now();
+        if let Some(group) = &daemon.group {
+            set_group(group)?;
+        }
+        if let Some(user) = &daemon.user {
+            set_user(user, daemon.privilege_regainable, daemon.set_user_env)?;
+        }
+        if daemon.group.is_some() || daemon.user.is_some() {
+            record_phase(profile, t, Phase::PrivilegeDrop);
+        }
+
+        // setuid/setgid clear the dumpable flag on Linux
+        #[cfg(target_os = "linux")]
+        if daemon.core_dump_dir.is_some() && libc::prctl(libc::PR_SET_DUMPABLE, 1, 0, 0, 0) < 0 {
+            return Err(DaemonError::SyscallError {
+                call: "prctl",
+                errno: io::Error::last_os_error().raw_os_error().unwrap_or(0),
+            });
+        }
+
+        if daemon.abort_if_root && libc::geteuid() == 0 {
+            return Err(DaemonError::PrivilegeError("refusing to run as root".to_owned()));
+        }
+
+        // --- Fully Initialized ---
+        if let Some(hook) = daemon.on_ready.take() {
+            hook()?;
+        }
+
+        // --- Readiness (READY_FD) & Launcher Status ---
+        // Last: the privilege drop and `on_ready` can still fail the daemon
+        if let Some(file) = ready_fd {
+            crate::notify::send_ready(file)?;
+        }
+        if let Some(mut writer) = status {
+            use std::io::Write;
+            writer.write_all(b"\0").map_err(|e| DaemonError::io("status pipe", e))?;
+        }
+
+        Ok(result)
+    }
+}
+
+/// Applies the `sigpipe` disposition.
+unsafe fn set_sigpipe(mode: SigpipeMode) -> DaemonResult<()> {
+    extern "C" fn ignore_sigpipe(_: libc::c_int) {}
+
+    let handler = match mode {
+        SigpipeMode::Ignore => libc::SIG_IGN,
+        SigpipeMode::Default => libc::SIG_DFL,
+        SigpipeMode::Error => ignore_sigpipe as extern "C" fn(libc::c_int) as libc::sighandler_t,
+    };
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGPIPE, &action, std::ptr::null_mut()) < 0 {
+            return Err(DaemonError::SyscallError {
+                call: "sigaction",
+                errno: io::Error::last_os_error().raw_os_error().unwrap_or(0),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the panic hook with one writing the panic and a forced backtrace to stderr,
+/// for `capture_backtrace`.
+fn install_backtrace_panic_hook() {
+    use std::io::Write;
+
+    std::panic::set_hook(Box::new(|info| {
+        let thread = std::thread::current().name().unwrap_or("<unnamed>").to_owned();
+        let backtrace = std::backtrace::Backtrace::force_capture();
+
+        let mut stderr = io::stderr().lock();
+        let _ = writeln!(stderr, "thread '{}' {}\nstack backtrace:\n{}", thread, info, backtrace);
+        let _ = stderr.flush();
+        // Fails harmlessly on /dev/null, pipes and sockets
+        unsafe { libc::fsync(libc::STDERR_FILENO) };
+    }));
+}
+
+/// Identifier passed to `openlog`; syslog keeps the pointer, so it must live forever.
+static SYSLOG_IDENT: OnceLock<CString> = OnceLock::new();
+
+/// Chains a panic hook reporting the panic to syslog at `LOG_CRIT`, for `panic_to_syslog`.
+fn install_syslog_panic_hook(name: Option<&str>) {
+    let ident = SYSLOG_IDENT.get_or_init(|| {
+        CString::new(name.unwrap_or("daemon_forge")).unwrap_or_else(|_| c"daemon_forge".to_owned())
+    });
+    unsafe { libc::openlog(ident.as_ptr(), libc::LOG_PID | libc::LOG_NDELAY, libc::LOG_DAEMON) };
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| info.payload().downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("Box<dyn Any>");
+        let location = info.location().map(|l| l.to_string()).unwrap_or_default();
+        let thread = std::thread::current().name().unwrap_or("<unnamed>").to_owned();
+
+        let mut lines = vec![format!("thread '{}' panicked at {}: {}", thread, location, message)];
+        let backtrace = std::backtrace::Backtrace::capture();
+        if backtrace.status() == std::backtrace::BacktraceStatus::Captured {
+            lines.extend(backtrace.to_string().lines().map(str::to_owned));
+        }
+        // One record per line: syslog daemons mangle embedded newlines
+        for line in lines {
+            if let Ok(line) = CString::new(line) {
+                unsafe { libc::syslog(libc::LOG_CRIT, c"%s".as_ptr(), line.as_ptr()) };
+            }
+        }
+
+        previous(info);
+    }));
+}
+
+/// Set by the `SIGHUP` dispatcher after each successful reload.
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Backs `daemon_forge::take_reload_request`.
+pub fn take_reload_request() -> bool {
+    RELOAD_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+/// Starts the thread that handles `SIGHUP`: runs the `on_reload` hook, then reopens
+/// stdio when `reopen` is set (`Some(nofollow)`), then flags the reload for the main loop.
+fn spawn_reload_dispatcher(
+    mut hook: Option<Box<dyn FnMut() -> DaemonResult<()> + Send>>,
+    reopen: Option<bool>,
+) -> DaemonResult<()> {
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP])
+        .map_err(|e| DaemonError::io("register SIGHUP", e))?;
+
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            // A failed reload keeps the previous configuration running
+            if let Some(hook) = hook.as_mut()
+                && let Err(e) = hook()
+            {
+                eprintln!("[DaemonForge] Reload failed: {}", e);
+                continue;
+            }
+            if let Some(nofollow) = reopen
+                && let Err(e) = reopen_stdio(nofollow)
+            {
+                eprintln!("[DaemonForge] Reopening stdio failed: {}", e);
+            }
+            RELOAD_REQUESTED.store(true, Ordering::Relaxed);
+        }
+    });
+    Ok(())
+}
+
+/// Starts the thread writing the `debug_signal` dump to stderr.
+fn spawn_debug_dumper(signal: libc::c_int, config: String) -> DaemonResult<()> {
+    use std::io::Write;
+
+    let mut signals = signal_hook::iterator::Signals::new([signal])
+        .map_err(|e| DaemonError::io("register debug signal", e))?;
+
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            let health = crate::process::health();
+            let mut err = io::stderr().lock();
+            let _ = writeln!(
+                err,
+                "[DaemonForge] Debug dump (signal {}):\n{:#?}\nConfiguration: {}",
+                signal, health, config
+            );
+            let _ = err.flush();
+        }
+    });
+    Ok(())
+}
+
+/// Read end of the `signal_pipe`; `-1` until it is installed.
+static SIGNAL_PIPE_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Backs `daemon_forge::signal_pipe_fd`.
+pub fn signal_pipe_fd() -> Option<std::os::unix::io::RawFd> {
+    Some(SIGNAL_PIPE_FD.load(Ordering::Relaxed)).filter(|fd| *fd >= 0)
+}
+
+/// Creates the `signal_pipe` and registers a handler writing each signal's number to it.
+fn install_signal_pipe(signals: &[libc::c_int]) -> DaemonResult<()> {
+    use std::os::unix::io::IntoRawFd;
+
+    let (reader, writer) = io::pipe().map_err(|e| DaemonError::io("pipe", e))?;
+    let (read_fd, write_fd) = (reader.into_raw_fd(), writer.into_raw_fd());
+    for fd in [read_fd, write_fd] {
+        // A full pipe must never block the handler, nor an empty one the event loop
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+            return Err(DaemonError::io("fcntl", io::Error::last_os_error()));
+        }
+    }
+
+    for &signal in signals {
+        let byte = signal as u8;
+        // Only async-signal-safe calls in the handler; the registry preserves errno
+        unsafe {
+            signal_hook::low_level::register(signal, move || {
+                libc::write(write_fd, (&byte as *const u8).cast(), 1);
+            })
+        }
+        .map_err(|e| DaemonError::io("register signal pipe", e))?;
+    }
+    SIGNAL_PIPE_FD.store(read_fd, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Reopens stdout/stderr at their recorded paths and swaps them in with `dup2`.
+/// A stream whose file can't be opened keeps its current target.
+fn reopen_stdio(nofollow: bool) -> DaemonResult<()> {
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+
+    let [stdout_path, stderr_path] = crate::stdio::stdio_paths();
+    let mut result = Ok(());
+
+    for (path, fd) in [(stdout_path, libc::STDOUT_FILENO), (stderr_path, libc::STDERR_FILENO)] {
+        let Some(path) = path else { continue };
+        let file = match crate::stdio::open_path(&path, false, nofollow) {
+            Ok(file) => file,
+            Err(e) => {
+                result = Err(DaemonError::io("reopen redirect file", e));
+                continue;
+            }
+        };
+
+        // Hold the std handle so buffered output is flushed to the old file first
+        let swapped = if fd == libc::STDOUT_FILENO {
+            let mut out = io::stdout().lock();
+            let _ = out.flush();
+            unsafe { retry_eintr!(libc::dup2(file.as_raw_fd(), fd)) }
+        } else {
+            let _err = io::stderr().lock();
+            unsafe { retry_eintr!(libc::dup2(file.as_raw_fd(), fd)) }
+        };
+        if swapped < 0 {
+            result = Err(DaemonError::io("dup2", io::Error::last_os_error()));
+        } else if fd == libc::STDOUT_FILENO && crate::stdio::stderr_merged() {
+            let _err = io::stderr().lock();
+            if unsafe { retry_eintr!(libc::dup2(libc::STDOUT_FILENO, libc::STDERR_FILENO)) } < 0 {
+                result = Err(DaemonError::io("dup2", io::Error::last_os_error()));
+            }
+        }
+
+        // The rotated-in file starts without a reservation
+        #[cfg(target_os = "linux")]
+        if swapped >= 0 {
+            let bytes = LOG_RESERVE_BYTES.load(Ordering::Relaxed);
+            if bytes > 0
+                && let Err(e) = reserve_log_space(fd, bytes)
+            {
+                result = Err(e);
+            }
+        }
+    }
+    result
+}
+
+/// Backs `daemon_forge::take_lock_fd`.
+pub fn take_lock_fd() -> Option<OwnedFd> {
+    let fd = LOCK_FD.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if fd.is_some() {
+        FCNTL_PID_FD.store(-1, Ordering::Relaxed);
+        crate::process::set_lock_held(false);
+    }
+    fd
+}
+
+/// Arms `die_with_parent`: `SIGTERM` is raised in this process once `parent` exits.
+#[cfg(target_os = "linux")]
+fn watch_parent(parent: libc::pid_t) -> DaemonResult<()> {
+    unsafe {
+        if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM, 0, 0, 0) < 0 {
+            return Err(DaemonError::SyscallError {
+                call: "prctl",
+                errno: io::Error::last_os_error().raw_os_error().unwrap_or(0),
+            });
+        }
+        // The parent may have exited before the signal was armed
+        if libc::getppid() != parent {
+            libc::kill(libc::getpid(), libc::SIGTERM);
+        }
+    }
+    Ok(())
+}
+
+/// Arms `die_with_parent`: a thread waits on a `kqueue` for `parent` to exit,
+/// then raises `SIGTERM` in this process.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd",
+    target_os = "netbsd"
+))]
+fn watch_parent(parent: libc::pid_t) -> DaemonResult<()> {
+    unsafe {
+        let kq = libc::kqueue();
+        if kq < 0 {
+            return Err(DaemonError::io("kqueue", io::Error::last_os_error()));
+        }
+
+        let mut change: libc::kevent = std::mem::zeroed();
+        change.ident = parent as _;
+        change.filter = libc::EVFILT_PROC as _;
+        change.flags = (libc::EV_ADD | libc::EV_ONESHOT) as _;
+        change.fflags = libc::NOTE_EXIT as _;
+
+        if libc::kevent(kq, &change, 1, std::ptr::null_mut(), 0, std::ptr::null()) < 0 {
+            let err = io::Error::last_os_error();
+            libc::close(kq);
+            // ESRCH: the parent is already gone
+            if err.raw_os_error() == Some(libc::ESRCH) {
+                libc::kill(libc::getpid(), libc::SIGTERM);
+                return Ok(());
+            }
+            return Err(DaemonError::io("kevent", err));
+        }
+
+        std::thread::spawn(move || {
+            let mut event: libc::kevent = std::mem::zeroed();
+            loop {
+                let n = libc::kevent(kq, std::ptr::null(), 0, &mut event, 1, std::ptr::null());
+                if n > 0 {
+                    libc::kill(libc::getpid(), libc::SIGTERM);
+                    break;
+                }
+                if n < 0 && io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                    break;
+                }
+            }
+            libc::close(kq);
+        });
+    }
+    Ok(())
+}
+
+/// `die_with_parent` is not supported on this platform.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd",
+    target_os = "netbsd"
+)))]
+fn watch_parent(_parent: libc::pid_t) -> DaemonResult<()> {
+    Err(DaemonError::ConfigError("die_with_parent is not supported on this platform".to_owned()))
+}
+
+/// Phase durations recorded when `profile_startup` is enabled.
+static STARTUP_TIMINGS: Mutex<Option<StartupTimings>> = Mutex::new(None);
+
+/// Startup phases tracked by `profile_startup`.
+enum Phase {
+    Fork,
+    Setsid,
+    Chdir,
+    Chroot,
+    PidFile,
+    PrivilegedAction,
+    PrivilegeDrop,
+}
+
+/// Adds the time elapsed since `since` to `phase`, if profiling is enabled.
+fn record_phase(enabled: bool, since: Instant, phase: Phase) {
+    if !enabled {
+        return;
+    }
+    let elapsed = since.elapsed();
+    let mut guard = STARTUP_TIMINGS.lock().unwrap_or_else(|e| e.into_inner());
+    let timings = guard.get_or_insert_with(StartupTimings::default);
+    let slot = match phase {
+        Phase::Fork => &mut timings.fork,
+        Phase::Setsid => &mut timings.setsid,
+        Phase::Chdir => &mut timings.chdir,
+        Phase::Chroot => &mut timings.chroot,
+        Phase::PidFile => &mut timings.pid_file,
+        Phase::PrivilegedAction => &mut timings.privileged_action,
+        Phase::PrivilegeDrop => &mut timings.privilege_drop,
+    };
+    *slot = Some(slot.unwrap_or_default() + elapsed);
+}
+
+/// Backs `daemon_forge::startup_timings`.
+pub fn startup_timings() -> Option<StartupTimings> {
+    *STARTUP_TIMINGS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Signal mask saved by `block_setup_signals`, restored by `restore_setup_signals`.
+static SETUP_SIGMASK: Mutex<Option<libc::sigset_t>> = Mutex::new(None);
+
+/// Blocks `SIGTERM`/`SIGINT` for `block_signals_during_setup`, saving the previous mask.
+fn block_setup_signals() -> DaemonResult<()> {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        let mut old: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGTERM);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        let rc = libc::pthread_sigmask(libc::SIG_BLOCK, &set, &mut old);
+        if rc != 0 {
+            return Err(DaemonError::io("sigprocmask", io::Error::from_raw_os_error(rc)));
+        }
+        *SETUP_SIGMASK.lock().unwrap_or_else(|e| e.into_inner()) = Some(old);
+    }
+    Ok(())
+}
+
+/// Restores the mask saved by `block_setup_signals`, if any. Safe to call repeatedly.
+fn restore_setup_signals() {
+    if let Some(old) = SETUP_SIGMASK.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        unsafe { libc::pthread_sigmask(libc::SIG_SETMASK, &old, std::ptr::null_mut()) };
+    }
+}
+
+/// Runs `f` with the mask saved by `block_setup_signals` in effect, then blocks the
+/// signals again. Runs `f` unchanged if they are not blocked.
+fn with_setup_signals_unblocked<R>(f: impl FnOnce() -> R) -> R {
+    let saved = *SETUP_SIGMASK.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(old) = saved else {
+        return f();
+    };
+    unsafe {
+        let mut blocked: libc::sigset_t = std::mem::zeroed();
+        libc::pthread_sigmask(libc::SIG_SETMASK, &old, &mut blocked);
+        let result = f();
+        libc::pthread_sigmask(libc::SIG_SETMASK, &blocked, std::ptr::null_mut());
+        result
+    }
+}
+
+/// Runs `f` under `mask` (`socket_umask`), if set, then restores the previous umask.
+fn with_umask<R>(mask: Option<u32>, f: impl FnOnce() -> R) -> R {
+    let previous = mask.map(|mask| unsafe { libc::umask(mask as libc::mode_t) });
+    let result = f();
+    if let Some(previous) = previous {
+        unsafe { libc::umask(previous) };
+    }
+    result
+}
+
+/// Returns `true` if the current process may write to `path` (`access(W_OK)`).
+pub fn is_writable(path: &Path) -> bool {
+    match path.to_str().and_then(|p| CString::new(p).ok()) {
+        Some(cpath) => unsafe { libc::access(cpath.as_ptr(), libc::W_OK) == 0 },
+        None => false,
+    }
+}
+
+/// Returns `true` if `path` can be written (or, when missing, created) by the identity the
+/// daemon runs as after dropping privileges: `user`'s UID, `group`'s GID or our own, and
+/// our supplementary groups, which the privilege drop keeps.
+///
+/// Compares the owner/group/other mode bits, so ACLs and read-only mounts are not seen.
+/// Without a `user`, falls back to `access(W_OK)` as the current process.
+pub fn is_writable_by(path: &Path, user: &Option<User>, group: &Option<Group>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    // As ourselves, the kernel can answer exactly
+    let as_current = || match path.exists() {
+        true => is_writable(path),
+        false => path.ancestors().skip(1).find(|p| p.exists()).is_some_and(is_writable),
+    };
+    let Some(user) = user else {
+        return as_current();
+    };
+    let lookup_uid = |name: &str| {
+        let cname = CString::new(name).ok()?;
+        let pwd = unsafe { libc::getpwnam(cname.as_ptr()) };
+        (!pwd.is_null()).then(|| unsafe { (*pwd).pw_uid })
+    };
+    let lookup_gid = |name: &str| {
+        let cname = CString::new(name).ok()?;
+        let grp = unsafe { libc::getgrnam(cname.as_ptr()) };
+        (!grp.is_null()).then(|| unsafe { (*grp).gr_gid })
+    };
+
+    let Some(uid) = lookup_uid(&user.0) else {
+        return false;
+    };
+    let gid = match group {
+        Some(group) => match lookup_gid(&group.0) {
+            Some(gid) => gid,
+            None => return false,
+        },
+        None => unsafe { libc::getegid() },
+    };
+    if uid == 0 {
+        return as_current();
+    }
+
+    let mut groups = vec![gid];
+    let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    if count > 0 {
+        let mut extra: Vec<libc::gid_t> = vec![0; count as usize];
+        let n = unsafe { libc::getgroups(count, extra.as_mut_ptr()) };
+        extra.truncate(n.max(0) as usize);
+        groups.extend(extra);
+    }
+
+    let allows = |meta: &std::fs::Metadata, bits: u32| {
+        let shift = if meta.uid() == uid {
+            6
+        } else if groups.contains(&meta.gid()) {
+            3
+        } else {
+            0
+        };
+        (meta.mode() >> shift) & bits == bits
+    };
+    match std::fs::metadata(path) {
+        Ok(meta) => allows(&meta, 0o2),
+        // Created in the nearest existing directory: needs write and search permission
+        Err(_) => path
+            .ancestors()
+            .skip(1)
+            .find_map(|p| std::fs::metadata(p).ok())
+            .is_some_and(|meta| allows(&meta, 0o3)),
+    }
+}
+
+/// Backs `ForgeDaemon::stop`: sends `SIGTERM` (or `SIGKILL` if `force`) to `pid`.
+/// Returns `false` if no such process exists.
+pub fn terminate(pid: u32, force: bool) -> DaemonResult<bool> {
+    // 0 and negative values would address process groups instead of a process
+    let Some(pid) = libc::pid_t::try_from(pid).ok().filter(|pid| *pid > 0) else {
+        return Err(DaemonError::io("read PID file", io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a valid PID", pid),
+        )));
+    };
+
+    let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+    if unsafe { libc::kill(pid, signal) } < 0 {
+        let err = io::Error::last_os_error();
+        return match err.raw_os_error() {
+            Some(libc::ESRCH) => Ok(false),
+            Some(libc::EPERM) => Err(DaemonError::PrivilegeError(format!("Not allowed to signal PID {}: {}", pid, err))),
+            errno => Err(DaemonError::SyscallError { call: "kill", errno: errno.unwrap_or(0) }),
+        };
+    }
+    Ok(true)
+}
+
+/// Liveness probe backing `daemon_forge::pid_is_alive`.
+pub fn pid_is_alive(pid: u32) -> bool {
+    // 0 and negative values would address process groups instead of a process
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid <= 0 {
+        return false;
+    }
+
+    if unsafe { libc::kill(pid, 0) } < 0 {
+        return io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    }
+
+    // A zombie still accepts signals but is no longer running
+    #[cfg(target_os = "linux")]
+    if let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        // The state follows the parenthesised command name, which may contain spaces
+        let state = stat.rsplit_once(')').and_then(|(_, rest)| rest.trim_start().chars().next());
+        if state == Some('Z') {
+            return false;
+        }
+    }
+
+    true
+}
+
+// =========================================================================
+// Helpers
+// =========================================================================
+
+/// An acquired PID file together with the open handle holding its lock.
+struct PidLock {
+    path: Option<PathBuf>,
+    /// Separate `lock_file` holding the lock; `None` when the PID file is the lock.
+    lock_path: Option<PathBuf>,
+    /// `additional_pid_files`, removed together with the PID file.
+    copies: Vec<PathBuf>,
+    file: File,
+    /// Hand the handle to a tamper-detection thread on `persist()`.
+    watch: bool,
+    style: LockStyle,
+}
+
+/// Descriptor number of the lock kept by `PidLock::persist`, for `reexec_on_upgrade`; `-1` if none.
+static PERSISTED_LOCK_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Lock handle kept alive by `PidLock::persist`, until claimed with `take_lock_fd`.
+static LOCK_FD: Mutex<Option<OwnedFd>> = Mutex::new(None);
+
+impl PidLock {
+    /// Keeps the file handle (and so the OS lock) alive for the lifetime of the process,
+    /// unless it is claimed with `take_lock_fd`.
+    fn persist(self) {
+        use std::os::unix::io::AsRawFd;
+
+        PERSISTED_LOCK_FD.store(self.file.as_raw_fd(), Ordering::Relaxed);
+        if let (true, Some(path)) = (self.watch, self.path) {
+            // The thread never returns, so it keeps the handle (and the lock) alive
+            let (file, separate_lock, style) = (self.file, self.lock_path.is_some(), self.style);
+            std::thread::spawn(move || watch_pid_file(path, file, separate_lock, style));
+        } else {
+            *LOCK_FD.lock().unwrap_or_else(|e| e.into_inner()) = Some(OwnedFd::from(self.file));
+        }
+    }
+
+    /// Removes the PID and lock files and releases the lock by closing the handle.
+    fn release(self) {
+        PID_HEARTBEAT_GEN.fetch_add(1, Ordering::Relaxed);
+        for path in self.path.iter().chain(&self.lock_path).chain(&self.copies) {
+            let _ = std::fs::remove_file(path);
+        }
+        FCNTL_PID_FD.store(-1, Ordering::Relaxed);
+        drop(self.file);
+        crate::process::set_lock_held(false);
+    }
+}
+
+/// Creates the PID file's parent directory with the configured mode if it is missing.
+fn create_pid_dir<T>(daemon: &ForgeDaemon<T>) -> DaemonResult<()> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    for path in daemon.lock_path().iter().chain(&daemon.lock_file).chain(&daemon.additional_pid_files) {
+        let path = daemon.resolve_path(path);
+        let Some(dir) = path.parent().filter(|dir| !dir.exists()) else {
+            continue;
+        };
+
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(daemon.pid_dir_mode)
+            .create(dir)
+            .map_err(|e| DaemonError::io("create PID directory", e))?;
+        // The inherited umask may have masked off some of the requested bits
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(daemon.pid_dir_mode))
+            .map_err(|e| DaemonError::io("chmod PID directory", e))?;
+    }
+    Ok(())
+}
+
+/// Creates the missing parent directories of the `Stdio::file` log paths, for `create_log_dirs`.
+fn create_log_dirs<T>(daemon: &ForgeDaemon<T>) -> DaemonResult<()> {
+    for stdio in [&daemon.stdout, &daemon.stderr] {
+        let Stdio::RedirectToPath(path) = stdio else { continue };
+        let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty() && !dir.exists()) else {
+            continue;
+        };
+        unsafe { create_owned_dir(dir, daemon.log_dir_mode, &daemon.user, &daemon.group)? };
+    }
+    Ok(())
+}
+
+fn apply_io_redirection<T>(daemon: &ForgeDaemon<T>) -> DaemonResult<()> {
+    unsafe {
+        redirect_stream(&daemon.stdin, libc::STDIN_FILENO, daemon.strict_devnull, daemon.logs_nofollow)?;
+        redirect_stream(&daemon.stdout, libc::STDOUT_FILENO, daemon.strict_devnull, daemon.logs_nofollow)?;
+        redirect_stream(&daemon.stderr, libc::STDERR_FILENO, daemon.strict_devnull, daemon.logs_nofollow)?;
+    }
+
+    #[cfg(target_os = "linux")]
+    if daemon.log_reserve_bytes > 0 {
+        LOG_RESERVE_BYTES.store(daemon.log_reserve_bytes, Ordering::Relaxed);
+        for fd in [libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+            reserve_log_space(fd, daemon.log_reserve_bytes)?;
+        }
+    }
+    Ok(())
+}
+
+/// Descriptors systemd passed for socket activation (`LISTEN_FDS`, from 3), if meant for us.
+#[cfg(target_os = "linux")]
+fn socket_activation_fds() -> std::ops::Range<libc::c_int> {
+    const SD_LISTEN_FDS_START: libc::c_int = 3;
+
+    let var = |name| std::env::var(name).ok().and_then(|v| v.parse::<libc::c_int>().ok());
+    match (var("LISTEN_PID"), var("LISTEN_FDS")) {
+        (Some(pid), Some(n)) if pid == unsafe { libc::getpid() } && n > 0 => SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + n,
+        _ => 0..0,
+    }
+}
+
+/// Clears `FD_CLOEXEC` on the `preserve_fd` descriptors, so they survive `exec`.
+fn clear_cloexec(fds: &[libc::c_int]) -> DaemonResult<()> {
+    for &fd in fds {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) } < 0 {
+            let e = io::Error::last_os_error();
+            return Err(DaemonError::io("preserve_fd", io::Error::new(e.kind(), format!("descriptor {}: {}", fd, e))));
+        }
+    }
+    Ok(())
+}
+
+/// Closes every descriptor above stderr not in `keep`, for `close_fds`.
+fn close_inherited_fds(keep: &[libc::c_int]) {
+    // Collected first: the directory handle must not be closed while iterating
+    let listed: Option<Vec<libc::c_int>> = std::fs::read_dir("/proc/self/fd").ok().map(|dir| {
+        dir.filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok()).collect()
+    });
+    let fds = listed.unwrap_or_else(|| {
+        let max = unsafe { libc::sysconf(libc::_SC_OPEN_MAX) };
+        let max = if max > 0 { max.min(libc::c_int::MAX as libc::c_long) as libc::c_int } else { 1024 };
+        (0..max).collect()
+    });
+
+    for fd in fds {
+        // EBADF for numbers that were never open (or the directory handle) is expected
+        if fd > libc::STDERR_FILENO && !keep.contains(&fd) {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+/// Fails with the list of open descriptors not in `expected`, for `assert_clean_fds`.
+#[cfg(target_os = "linux")]
+fn check_clean_fds(expected: &[libc::c_int]) -> DaemonResult<()> {
+    let fds: Vec<libc::c_int> = std::fs::read_dir("/proc/self/fd")
+        .map_err(|e| DaemonError::io("read /proc/self/fd", e))?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+
+    // The directory handle itself was listed too; it is closed by now
+    let leaked: Vec<String> = fds
+        .into_iter()
+        .filter(|fd| !expected.contains(fd) && unsafe { libc::fcntl(*fd, libc::F_GETFD) } >= 0)
+        .map(|fd| match std::fs::read_link(format!("/proc/self/fd/{}", fd)) {
+            Ok(target) => format!("{} ({})", fd, target.display()),
+            Err(_) => fd.to_string(),
+        })
+        .collect();
+
+    if leaked.is_empty() {
+        Ok(())
+    } else {
+        Err(DaemonError::PrivilegeError(format!(
+            "Unexpected file descriptors open in the daemon: {}",
+            leaked.join(", ")
+        )))
+    }
+}
+
+/// `log_reserve_bytes`, kept for `reopen_stdio_on_reload`; `0` if none.
+#[cfg(target_os = "linux")]
+static LOG_RESERVE_BYTES: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+/// Preallocates `bytes` past the end of `fd` without changing its size, if it is a
+/// regular file (terminals, pipes and `/dev/null` are left alone).
+#[cfg(target_os = "linux")]
+fn reserve_log_space(fd: libc::c_int, bytes: u64) -> DaemonResult<()> {
+    let mut st: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut st) } < 0 {
+        return Err(DaemonError::io("fstat", io::Error::last_os_error()));
+    }
+    if st.st_mode & libc::S_IFMT != libc::S_IFREG {
+        return Ok(());
+    }
+
+    let len = bytes.min(libc::off_t::MAX as u64) as libc::off_t;
+    if unsafe { retry_eintr!(libc::fallocate(fd, libc::FALLOC_FL_KEEP_SIZE, st.st_size, len)) } < 0 {
+        return Err(DaemonError::io("reserve log space", io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// Debug-build check: forking a multithreaded process (e.g. after building a
+/// Tokio runtime) only duplicates the calling thread and can deadlock the child.
+#[cfg(all(debug_assertions, target_os = "linux"))]
+fn warn_if_multithreaded() {
+    let threads = std::fs::read_dir("/proc/self/task")
+        .map(|entries| entries.count())
+        .unwrap_or(1);
+    if threads > 1 {
+        eprintln!(
+            "[DaemonForge Warning] Forking a process with {} threads. Only the calling thread \
+             survives the fork; call start() before spawning threads or async runtimes.",
+            threads
+        );
+    }
+}
+
+/// Blocks until the write end of the pipe is closed everywhere or `timeout` elapses.
+fn wait_for_hangup(reader: &io::PipeReader, timeout: Duration) {
+    use std::os::unix::io::AsRawFd;
+
+    let mut pfd = libc::pollfd {
+        fd: reader.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let millis = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+    unsafe { libc::poll(&mut pfd, 1, millis) };
+}
+
+/// Blocks until the daemon reports on the status pipe.
+/// Returns `true` if it signalled success, `false` if the pipe closed without a byte.
+fn wait_for_status(mut reader: io::PipeReader) -> bool {
+    use std::io::Read;
+
+    let mut byte = [0u8; 1];
+    loop {
+        match reader.read(&mut byte) {
+            Ok(n) => return n == 1,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => return false,
+        }
+    }
+}
+
+unsafe fn perform_fork() -> DaemonResult<libc::pid_t> {
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        Err(DaemonError::SyscallError {
+            call: "fork",
+            errno: io::Error::last_os_error().raw_os_error().unwrap_or(0),
+        })
+    } else {
+        Ok(pid)
+    }
+}
 
-#[cfg(target_os = "linux")]
-use sd_notify::NotifyState;
+unsafe fn redirect_stream(
+    stdio: &Stdio,
+    target_fd: libc::c_int,
+    strict_devnull: bool,
+    nofollow: bool,
+) -> DaemonResult<()> {
+    use std::os::unix::io::AsRawFd;
 
-/// Main entry point for Unix systems.
-/// 
-/// It automatically detects if the process is being managed by Systemd (via `NOTIFY_SOCKET`).
-/// - **Systemd Detected:** Runs in the foreground, notifies `READY=1`, and executes the payload.
-/// - **Manual Start:** Performs the classic double-fork machination to daemonize into the background.
-pub fn start<T>(daemon: ForgeDaemon<T>) -> DaemonResult<T> {
-    
-    #[cfg(target_os = "linux")]
-    {
-        // If NOTIFY_SOCKET is present, Systemd expects us to stay in the foreground
-        if std::env::var("NOTIFY_SOCKET").is_ok() {
-            return start_systemd_mode(daemon);
+    match stdio {
+        Stdio::RedirectToFile(f) => {
+            if unsafe { retry_eintr!(libc::dup2(f.as_raw_fd(), target_fd)) } < 0 {
+                return Err(DaemonError::io("dup2", io::Error::last_os_error()));
+            }
+        }
+        Stdio::RedirectToPath(path) => {
+            let file = crate::stdio::open_path(path, target_fd == libc::STDIN_FILENO, nofollow)
+                .map_err(|e| DaemonError::io("open redirect file", e))?;
+            if unsafe { retry_eintr!(libc::dup2(file.as_raw_fd(), target_fd)) } < 0 {
+                return Err(DaemonError::io("dup2", io::Error::last_os_error()));
+            }
+            if target_fd != libc::STDIN_FILENO {
+                crate::stdio::record_stdio_path((target_fd - libc::STDOUT_FILENO) as usize, path);
+            }
+        }
+        Stdio::TcpStream(stream) => {
+            if unsafe { retry_eintr!(libc::dup2(stream.as_raw_fd(), target_fd)) } < 0 {
+                return Err(DaemonError::io("dup2", io::Error::last_os_error()));
+            }
+        }
+        Stdio::Devnull => {
+            let path = CString::new("/dev/null").unwrap();
+            let fd = unsafe { retry_eintr!(libc::open(path.as_ptr(), libc::O_RDWR)) };
+            if fd < 0 {
+                if strict_devnull {
+                    return Err(DaemonError::io("open /dev/null", io::Error::last_os_error()));
+                }
+                return unsafe { redirect_to_dead_pipe(target_fd) };
+            }
+
+            if unsafe { retry_eintr!(libc::dup2(fd, target_fd)) } < 0 {
+                return Err(DaemonError::io("dup2", io::Error::last_os_error()));
+            }
+
+            unsafe { libc::close(fd) };
         }
+        Stdio::Keep => {}
+        Stdio::SameAsStdout => {
+            if target_fd != libc::STDERR_FILENO {
+                return Err(DaemonError::ConfigError("Stdio::merge() is only valid for stderr".to_owned()));
+            }
+            // stdout is redirected first, so this shares its final target
+            if unsafe { retry_eintr!(libc::dup2(libc::STDOUT_FILENO, target_fd)) } < 0 {
+                return Err(DaemonError::io("dup2", io::Error::last_os_error()));
+            }
+            crate::stdio::set_stderr_merged();
+        }
+    }
+    Ok(())
+}
+
+/// Fallback for sandboxes without a null device: points `target_fd` at one end of
+/// a pipe whose other end is closed. Reads see EOF; writes fail with `EPIPE`
+/// (Rust ignores `SIGPIPE`) instead of blocking.
+unsafe fn redirect_to_dead_pipe(target_fd: libc::c_int) -> DaemonResult<()> {
+    let mut fds = [0 as libc::c_int; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+        return Err(DaemonError::io("pipe", io::Error::last_os_error()));
     }
+    let [read_end, write_end] = fds;
+    let (keep, other) = if target_fd == libc::STDIN_FILENO {
+        (read_end, write_end)
+    } else {
+        (write_end, read_end)
+    };
 
-    start_background_mode(daemon)
+    unsafe { libc::close(other) };
+    // The pipe may already occupy target_fd if it was closed before
+    if keep != target_fd {
+        if unsafe { retry_eintr!(libc::dup2(keep, target_fd)) } < 0 {
+            return Err(DaemonError::io("dup2", io::Error::last_os_error()));
+        }
+        unsafe { libc::close(keep) };
+    }
+    Ok(())
 }
 
+/// Returns `true` if `path` still refers to the open `file`.
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
 
-#[cfg(target_os = "linux")]
-fn start_systemd_mode<T>(daemon: ForgeDaemon<T>) -> DaemonResult<T> {
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
 
-    apply_io_redirection(&daemon)?;
+/// Opens (creating if needed, never truncating) the PID file read-write without following symlinks.
+///
+/// The file is opened with `openat` relative to a handle on its directory, so the
+/// directory can't be swapped between resolving it and creating the file.
+fn open_pid_file(path: &Path) -> DaemonResult<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
 
-    // Notify Systemd that the service is ready.
-    // 'true' tells the library to unset the env var so it doesn't leak to children.
-    let _ = sd_notify::notify(true, &[NotifyState::Ready]);
+    let (dir, file_name) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => (dir, name),
+        _ => return Err(DaemonError::ConfigError(format!("Invalid PID file path: {:?}", path))),
+    };
+    let file_name = CString::new(file_name.as_encoded_bytes())
+        .map_err(|_| DaemonError::ConfigError(format!("Invalid PID file path: {:?}", path)))?;
 
-    execute_daemon_logic(daemon)
-}
+    let dir = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY)
+        .open(dir)
+        .map_err(|e| DaemonError::io("open PID directory", e))?;
 
-/// Double-Fork to detach from terminal and run in background.
-fn start_background_mode<T>(daemon: ForgeDaemon<T>) -> DaemonResult<T> {
-    unsafe {
-        // Fork 1
-        if perform_fork()? > 0 {
-            exit(0);
+    let fd = unsafe {
+        retry_eintr!(libc::openat(
+            dir.as_raw_fd(),
+            file_name.as_ptr(),
+            libc::O_RDWR | libc::O_CREAT | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            0o666 as libc::c_uint,
+        ))
+    };
+    if fd < 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::ELOOP) {
+            return Err(DaemonError::ConfigError(format!(
+                "PID file {:?} is a symlink; refusing to follow it",
+                path
+            )));
         }
+        return Err(DaemonError::io("open PID file", err));
+    }
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
 
-        // New Session
-        if libc::setsid() < 0 {
-            return Err(DaemonError::SyscallError {
-                call: "setsid",
-                errno: io::Error::last_os_error().raw_os_error().unwrap_or(0),
-            });
-        }
+/// `pid_sync_barrier`: flushes the PID file, then its directory entry.
+fn sync_pid_file(file: &File, path: &Path) -> DaemonResult<()> {
+    file.sync_all().map_err(|e| DaemonError::io("fsync PID file", e))?;
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|e| DaemonError::io("fsync PID directory", e))
+}
 
-        // IO Redirection
-        apply_io_redirection(&daemon)?;
+/// Applies `pid_file_mode`, which the process umask may have narrowed at creation.
+fn set_pid_file_mode(file: &File, mode: u32) -> DaemonResult<()> {
+    use std::os::unix::fs::PermissionsExt;
 
-        // Fork 2
-        if perform_fork()? > 0 {
-            exit(0);
-        }
+    file.set_permissions(std::fs::Permissions::from_mode(mode))
+        .map_err(|e| DaemonError::io("chmod PID file", e))
+}
 
-        // Execute the main daemon logic in the grandchild process
-        execute_daemon_logic(daemon)
+/// Resolves `path` against the current directory, so the file can be removed later
+/// even if the working directory changes.
+fn absolute_path(path: &Path) -> DaemonResult<PathBuf> {
+    if path.is_relative() {
+        Ok(std::env::current_dir()
+            .map_err(|e| DaemonError::io("resolve PID file path", e))?
+            .join(path))
+    } else {
+        Ok(path.to_path_buf())
     }
 }
 
-/// The core execution logic common to both Systemd and Background modes.
-/// Handles environment, chroot, PID files, privileges, and the user action.
-fn execute_daemon_logic<T>(daemon: ForgeDaemon<T>) -> DaemonResult<T> {
-    unsafe {
-        // --- Environment Management ---
-        if daemon.clear_env {
-            #[cfg(target_os = "linux")]
-            libc::clearenv();
+/// Takes an exclusive lock of the given `style` on `fd`, waiting for it if `block`.
+unsafe fn lock_fd(fd: libc::c_int, style: LockStyle, block: bool) -> io::Result<()> {
+    let ret = match style {
+        LockStyle::Flock => {
+            let op = if block { libc::LOCK_EX } else { libc::LOCK_EX | libc::LOCK_NB };
+            unsafe { retry_eintr!(libc::flock(fd, op)) }
         }
-        for (k, v) in &daemon.env_vars {
-            std::env::set_var(k, v);
+        LockStyle::Fcntl => {
+            // Whole file: l_start = 0, l_len = 0 (to EOF, however it grows)
+            let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+            lock.l_type = libc::F_WRLCK as _;
+            lock.l_whence = libc::SEEK_SET as _;
+            let cmd = if block { libc::F_SETLKW } else { libc::F_SETLK };
+            unsafe { retry_eintr!(libc::fcntl(fd, cmd, &lock)) }
         }
+    };
+    if ret < 0 { Err(io::Error::last_os_error()) } else { Ok(()) }
+}
 
-        // --- System Configuration ---
-        if let Some(mask) = daemon.umask {
-            libc::umask(mask as libc::mode_t);
+/// Reads the whole file through `file` with positioned reads, leaving its offset alone.
+///
+/// Opening the path instead would release a `LockStyle::Fcntl` lock held on it when closed.
+fn read_handle(file: &File) -> io::Result<String> {
+    use std::os::unix::fs::FileExt;
+
+    let mut content = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+        match file.read_at(&mut buf, content.len() as u64) {
+            Ok(0) => break,
+            Ok(n) => content.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
         }
+    }
+    String::from_utf8(content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
 
-        let cwd = CString::new(daemon.directory.to_str().unwrap()).map_err(|_| {
-            DaemonError::Io(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Invalid CWD path",
-            ))
-        })?;
-        if libc::chdir(cwd.as_ptr()) < 0 {
-            return Err(DaemonError::Io(io::Error::last_os_error()));
+/// Descriptor of the PID file while it carries a `LockStyle::Fcntl` lock, so `health()`
+/// can read it without closing a descriptor of the file; `-1` otherwise.
+static FCNTL_PID_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Reads the PID file through the locked descriptor when it carries a `LockStyle::Fcntl`
+/// lock; `None` if it doesn't and the path can be read directly.
+pub(crate) fn read_fcntl_pid_file() -> Option<io::Result<String>> {
+    use std::os::unix::io::FromRawFd;
+
+    let fd = FCNTL_PID_FD.load(Ordering::Relaxed);
+    if fd < 0 {
+        return None;
+    }
+    // Borrowed: the descriptor stays owned by the `PidLock` or the watcher
+    let file = std::mem::ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    Some(read_handle(&file))
+}
+
+/// Opens `path` and takes its lock, honouring `leader_election` and `lock_style`.
+unsafe fn acquire_lock<T>(path: &Path, daemon: &ForgeDaemon<T>) -> DaemonResult<File> {
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    // After `reexec_on_upgrade` we already hold the lock through the inherited descriptor
+    let inherited = INHERITED_LOCK_FD.swap(-1, Ordering::Relaxed);
+    if inherited >= 0 {
+        let file = unsafe { File::from_raw_fd(inherited) };
+        if is_same_file(&file, path) {
+            let flags = unsafe { libc::fcntl(inherited, libc::F_GETFD) };
+            unsafe { libc::fcntl(inherited, libc::F_SETFD, flags | libc::FD_CLOEXEC) };
+            return Ok(file);
         }
+    }
 
-        // --- Chroot Logic ---
-        if let Some(root) = &daemon.root {
-            let root_c = CString::new(root.to_str().unwrap()).map_err(|_| {
-                DaemonError::Io(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "Invalid chroot path",
-                ))
-            })?;
-            if libc::chroot(root_c.as_ptr()) < 0 {
-                return Err(DaemonError::PrivilegeError(format!(
-                    "chroot failed: {}",
-                    io::Error::last_os_error()
-                )));
-            }
-            // Always change dir to "/" after chroot
-            if libc::chdir(b"/\0".as_ptr() as *const i8) < 0 {
-                return Err(DaemonError::Io(io::Error::last_os_error()));
+    loop {
+        // Not truncated on open: the running instance's PID must survive until we hold the lock
+        let file = open_pid_file(path)?;
+
+        let fd = file.as_raw_fd();
+
+        if daemon.leader_election {
+            // Standby: block until the current leader releases the lock (exits). The wait
+            // has no time limit, so stop requests must be able to end it.
+            if let Err(e) = with_setup_signals_unblocked(|| unsafe { lock_fd(fd, daemon.lock_style, true) }) {
+                return Err(DaemonError::io("lock PID file", e));
             }
+        } else if unsafe { lock_fd(fd, daemon.lock_style, false) }.is_err() {
+            // Non-blocking: don't wait if another instance is running
+            return Err(DaemonError::TargetLocked);
         }
 
-        // --- Locking & PID File Logic ---
-        let effective_lock_path = if let Some(path) = &daemon.pid_file {
-            Some(path.clone())
-        } else if let Some(name) = &daemon.name {
-            Some(std::env::temp_dir().join(format!("daemon-{}.pid", name)))
-        } else {
-            None
+        // The previous holder may have unlinked the file while we waited; a lock
+        // on the orphaned inode would protect nothing, so start over.
+        if is_same_file(&file, path) {
+            return Ok(file);
+        }
+    }
+}
+
+/// Takes the instance lock and writes the PID to `path`.
+///
+/// The lock is held on `path` itself, or on the `lock_file` when one is configured,
+/// in which case the PID file is written without a lock (and `path` may be `None`).
+unsafe fn write_pid_file_unix<T>(path: Option<&Path>, daemon: &ForgeDaemon<T>) -> DaemonResult<PidLock> {
+    use std::io::Write;
+
+    let path = path.map(absolute_path).transpose()?;
+    let lock_path = daemon.lock_file.as_deref().map(absolute_path).transpose()?;
+
+    let file = match (&lock_path, &path) {
+        (Some(lock_path), _) | (None, Some(lock_path)) => {
+            let file = unsafe { acquire_lock(lock_path, daemon)? };
+            set_pid_file_mode(&file, daemon.pid_file_mode)?;
+            file
+        }
+        (None, None) => return Err(DaemonError::ConfigError(
+            "additional_pid_files requires a PID or lock file to hold the lock".to_owned(),
+        )),
+    };
+
+    if let Some(path) = &path {
+        // Written through the lock handle itself: closing a second descriptor of the
+        // file would release a `LockStyle::Fcntl` lock
+        let separate = match lock_path {
+            Some(_) => {
+                let pid_file = open_pid_file(path)?;
+                set_pid_file_mode(&pid_file, daemon.pid_file_mode)?;
+                Some(pid_file)
+            }
+            None => None,
         };
+        let mut pid_file = separate.as_ref().unwrap_or(&file);
+        let pid = unsafe { libc::getpid() };
+        pid_file
+            .set_len(0)
+            .and_then(|_| write!(pid_file, "{}", pid))
+            .map_err(|e| DaemonError::io("write PID file", e))?;
+        if daemon.pid_sync_barrier {
+            sync_pid_file(pid_file, path)?;
+        }
+        crate::process::set_recorded_pid(pid as u32, path.clone());
 
-        if let Some(path) = effective_lock_path {
-            write_pid_file_unix(&path)?;
-            if daemon.chown_pid {
-                apply_chown(&path, &daemon.user, &daemon.group)?;
+        // Guards against fork-ordering regressions recording an intermediate PID
+        if daemon.verify_pid {
+            let recorded = pid_file
+                .sync_all()
+                .and_then(|_| read_handle(pid_file))
+                .map_err(|e| DaemonError::io("verify PID file", e))?;
+            if recorded.trim().parse::<libc::pid_t>().ok() != Some(pid) {
+                return Err(DaemonError::io("verify PID file", io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("PID file contains '{}' but the daemon PID is {}", recorded.trim(), pid),
+                )));
             }
         }
+    }
+    crate::process::set_lock_held(true);
+    if daemon.lock_style == LockStyle::Fcntl && lock_path.is_none() {
+        use std::os::unix::io::AsRawFd;
+        FCNTL_PID_FD.store(file.as_raw_fd(), Ordering::Relaxed);
+    }
 
-        // --- Privileged Action (Payload) ---
-        // This is where the user's loop runs
-        let action = daemon.privileged_action.unwrap();
-        let result = action()?;
+    // Informational copies: written without a lock, after ours is held
+    let copies = daemon.additional_pid_files.iter().map(|p| absolute_path(p)).collect::<DaemonResult<Vec<_>>>()?;
+    let pid = std::process::id().to_string();
+    for copy in &copies {
+        let mut f = open_pid_file(copy)?;
+        set_pid_file_mode(&f, daemon.pid_file_mode)?;
+        f.set_len(0)
+            .and_then(|_| f.write_all(pid.as_bytes()))
+            .map_err(|e| DaemonError::io("write additional PID file", e))?;
+        if daemon.pid_sync_barrier {
+            sync_pid_file(&f, copy)?;
+        }
+    }
 
-        // --- Drop Privileges ---
-        // (Only executed if the action returns, usually cleanup)
-        if let Some(group) = &daemon.group {
-            set_group(group)?;
+    Ok(PidLock { path, lock_path, copies, file, watch: daemon.detect_pid_tampering, style: daemon.lock_style })
+}
+
+/// How often `detect_pid_tampering` checks the PID file.
+const PID_TAMPER_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Tamper-detection loop: rewrites the PID file when its content changed, and
+/// recreates and relocks it when it was removed or replaced by another inode.
+///
+/// With a `separate_lock`, `file` is the lock file: the PID file is only rewritten.
+fn watch_pid_file(path: PathBuf, mut file: File, separate_lock: bool, style: LockStyle) {
+    use std::os::unix::fs::{FileExt, OpenOptionsExt};
+    use std::os::unix::io::AsRawFd;
+
+    let pid = std::process::id();
+    loop {
+        std::thread::sleep(PID_TAMPER_INTERVAL);
+
+        let same_file = separate_lock || is_same_file(&file, &path);
+        // Through the locked handle when it is the PID file, which keeps an fcntl lock
+        let content = if separate_lock { std::fs::read_to_string(&path) } else { read_handle(&file) };
+        let recorded = content.ok().and_then(|s| s.trim().parse::<u32>().ok());
+        if same_file && recorded == Some(pid) {
+            continue;
         }
-        if let Some(user) = &daemon.user {
-            set_user(user)?;
+
+        if separate_lock {
+            let rewritten = open_pid_file(&path).is_ok_and(|f| {
+                f.set_len(0).and_then(|_| f.write_all_at(pid.to_string().as_bytes(), 0)).is_ok()
+            });
+            if rewritten {
+                eprintln!("[DaemonForge] PID file {:?} was tampered with; rewrote it", path);
+            }
+            continue;
         }
 
-        Ok(result)
+        if !same_file {
+            let replacement = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .custom_flags(libc::O_NOFOLLOW)
+                .open(&path);
+            match replacement {
+                // Don't fight another process that legitimately holds the new file's lock
+                Ok(f) if unsafe { lock_fd(f.as_raw_fd(), style, false) }.is_ok() => {
+                    PERSISTED_LOCK_FD.store(f.as_raw_fd(), Ordering::Relaxed);
+                    if style == LockStyle::Fcntl {
+                        FCNTL_PID_FD.store(f.as_raw_fd(), Ordering::Relaxed);
+                    }
+                    file = f;
+                }
+                _ => continue,
+            }
+        }
+
+        // Positioned write: the handle's offset is past the end after a truncation
+        if file.set_len(0).and_then(|_| file.write_all_at(pid.to_string().as_bytes(), 0)).is_ok() {
+            eprintln!("[DaemonForge] PID file {:?} was tampered with; rewrote it", path);
+        }
     }
 }
 
-// =========================================================================
-// Helpers
-// =========================================================================
+/// Bumped when the PID file is removed, ending the `pid_heartbeat` thread started before.
+static PID_HEARTBEAT_GEN: AtomicU32 = AtomicU32::new(0);
 
-fn apply_io_redirection<T>(daemon: &ForgeDaemon<T>) -> DaemonResult<()> {
-    unsafe {
-        redirect_stream(&daemon.stdin, libc::STDIN_FILENO)?;
-        redirect_stream(&daemon.stdout, libc::STDOUT_FILENO)?;
-        redirect_stream(&daemon.stderr, libc::STDERR_FILENO)?;
+/// Starts the `pid_heartbeat` thread, touching `path`'s modification time every `interval`.
+fn spawn_pid_heartbeat(path: PathBuf, interval: Duration) {
+    use std::os::unix::ffi::OsStrExt;
+
+    let generation = PID_HEARTBEAT_GEN.load(Ordering::Relaxed);
+    let Ok(path_c) = CString::new(path.as_os_str().as_bytes()) else {
+        return;
+    };
+    std::thread::spawn(move || {
+        let mut warned = false;
+        loop {
+            std::thread::sleep(interval);
+            if PID_HEARTBEAT_GEN.load(Ordering::Relaxed) != generation {
+                return;
+            }
+            // Null times: now, for both access and modification
+            let rc = unsafe { libc::utimensat(libc::AT_FDCWD, path_c.as_ptr(), std::ptr::null(), libc::AT_SYMLINK_NOFOLLOW) };
+            if rc < 0 && !warned {
+                eprintln!("[DaemonForge] PID heartbeat can't touch {:?}: {}", path, io::Error::last_os_error());
+                warned = true;
+            }
+        }
+    });
+}
+
+/// Applies `ionice` to the calling thread with `ioprio_set(IOPRIO_WHO_PROCESS, 0, ...)`.
+#[cfg(target_os = "linux")]
+fn set_io_priority(class: IoClass) -> DaemonResult<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: u32 = 13;
+
+    let (class, data) = match class {
+        IoClass::Realtime(prio) => (1, prio.min(7)),
+        IoClass::BestEffort(prio) => (2, prio.min(7)),
+        IoClass::Idle => (3, 0),
+    };
+    let ioprio = (class << IOPRIO_CLASS_SHIFT) | data as u32;
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio as libc::c_int) } < 0 {
+        let err = io::Error::last_os_error();
+        return Err(match err.raw_os_error() {
+            Some(libc::EPERM) => DaemonError::PrivilegeError(format!("Failed to set the IO priority: {}", err)),
+            errno => DaemonError::SyscallError { call: "ioprio_set", errno: errno.unwrap_or(0) },
+        });
     }
     Ok(())
 }
 
-unsafe fn perform_fork() -> DaemonResult<libc::pid_t> {
-    let pid = unsafe { libc::fork() };
-    if pid < 0 {
-        Err(DaemonError::SyscallError {
-            call: "fork",
-            errno: io::Error::last_os_error().raw_os_error().unwrap_or(0),
-        })
-    } else {
-        Ok(pid)
+/// Writes `/proc/self/oom_score_adj` for `oom_score_adj`.
+#[cfg(target_os = "linux")]
+fn set_oom_score_adj(adj: i32) -> DaemonResult<()> {
+    match std::fs::write("/proc/self/oom_score_adj", adj.to_string()) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Err(DaemonError::PrivilegeError(format!(
+            "Failed to set oom_score_adj to {}: {}",
+            adj, e
+        ))),
+        Err(e) => Err(DaemonError::io("write oom_score_adj", e)),
     }
 }
 
-unsafe fn redirect_stream(stdio: &Stdio, target_fd: libc::c_int) -> DaemonResult<()> {
-    use std::os::unix::io::AsRawFd;
+/// Directory the crash handler changes into before the core is written.
+static CORE_DUMP_DIR: OnceLock<CString> = OnceLock::new();
 
-    match stdio {
-        Stdio::RedirectToFile(f) => {
-            if unsafe { libc::dup2(f.as_raw_fd(), target_fd) } < 0 {
-                return Err(DaemonError::Io(io::Error::last_os_error()));
-            }
+/// Signals whose default action produces a core dump.
+const CRASH_SIGNALS: [libc::c_int; 5] = [libc::SIGSEGV, libc::SIGBUS, libc::SIGILL, libc::SIGFPE, libc::SIGABRT];
+
+/// The dispositions the crash handler replaced, in `CRASH_SIGNALS` order. It chains to
+/// them, e.g. to the Rust runtime's handler reporting stack overflows on `SIGSEGV`/`SIGBUS`.
+static PREVIOUS_CRASH_ACTIONS: OnceLock<[libc::sigaction; CRASH_SIGNALS.len()]> = OnceLock::new();
+
+/// Raises `RLIMIT_CORE` and installs the crash handlers for `enable_core_dumps`.
+unsafe fn enable_core_dumps(dir: &Path) -> DaemonResult<()> {
+    let dir_c = CString::new(dir.to_str().unwrap_or_default()).map_err(|_| {
+        DaemonError::ConfigError(format!("Invalid core dump directory: {:?}", dir))
+    })?;
+    let _ = CORE_DUMP_DIR.set(dir_c);
+
+    unsafe {
+        let mut limit: libc::rlimit = std::mem::zeroed();
+        if libc::getrlimit(libc::RLIMIT_CORE, &mut limit) < 0 {
+            return Err(DaemonError::io("getrlimit", io::Error::last_os_error()));
         }
-        Stdio::Devnull => {
-            let path = CString::new("/dev/null").unwrap();
-            let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDWR) };
-            if fd < 0 {
-                return Err(DaemonError::Io(io::Error::last_os_error()));
+        let unlimited = libc::rlimit { rlim_cur: libc::RLIM_INFINITY, rlim_max: libc::RLIM_INFINITY };
+        if libc::setrlimit(libc::RLIMIT_CORE, &unlimited) < 0 {
+            // Unprivileged: only the soft limit can be raised, up to the hard limit
+            limit.rlim_cur = limit.rlim_max;
+            if libc::setrlimit(libc::RLIMIT_CORE, &limit) < 0 {
+                return Err(DaemonError::io("setrlimit", io::Error::last_os_error()));
             }
+        }
 
-            if unsafe { libc::dup2(fd, target_fd) } < 0 {
-                return Err(DaemonError::Io(io::Error::last_os_error()));
+        let mut previous: [libc::sigaction; CRASH_SIGNALS.len()] = std::mem::zeroed();
+        for (sig, old) in CRASH_SIGNALS.into_iter().zip(&mut previous) {
+            if libc::sigaction(sig, std::ptr::null(), old) < 0 {
+                return Err(DaemonError::io("sigaction", io::Error::last_os_error()));
             }
+        }
+        let _ = PREVIOUS_CRASH_ACTIONS.set(previous);
 
-            unsafe { libc::close(fd) };
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = crash_handler as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void)
+            as libc::sighandler_t;
+        // SA_ONSTACK: a stack overflow leaves only the alternate stack the runtime set up
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK | libc::SA_NODEFER;
+        libc::sigemptyset(&mut action.sa_mask);
+        for sig in CRASH_SIGNALS {
+            if libc::sigaction(sig, &action, std::ptr::null_mut()) < 0 {
+                return Err(DaemonError::io("sigaction", io::Error::last_os_error()));
+            }
         }
-        Stdio::Keep => {}
     }
     Ok(())
 }
 
-unsafe fn write_pid_file_unix(path: &Path) -> DaemonResult<()> {
-    use std::io::Write;
-    use std::os::unix::io::AsRawFd;
-
-    let file = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(path)?;
-
-    let fd = file.as_raw_fd();
+/// Crash handler: moves into the core dump directory, then chains to the previous handler,
+/// or re-raises the signal with the default (core dumping) action if there was none.
+/// Only async-signal-safe calls are made here.
+extern "C" fn crash_handler(sig: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    unsafe {
+        if let Some(dir) = CORE_DUMP_DIR.get() {
+            libc::chdir(dir.as_ptr());
+        }
 
-    // LOCK_NB ensures we don't block if another instance is running
-    if unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) } < 0 {
-        return Err(DaemonError::TargetLocked);
+        let previous = CRASH_SIGNALS
+            .iter()
+            .position(|s| *s == sig)
+            .and_then(|i| PREVIOUS_CRASH_ACTIONS.get().map(|actions| actions[i]));
+        match previous {
+            Some(prev) if prev.sa_sigaction != libc::SIG_DFL && prev.sa_sigaction != libc::SIG_IGN => {
+                // A handler returning (as the runtime's does for faults it doesn't own, after
+                // restoring the default action) re-executes the faulting instruction
+                if prev.sa_flags & libc::SA_SIGINFO != 0 {
+                    let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                        std::mem::transmute(prev.sa_sigaction);
+                    handler(sig, info, context);
+                } else {
+                    let handler: extern "C" fn(libc::c_int) = std::mem::transmute(prev.sa_sigaction);
+                    handler(sig);
+                }
+            }
+            _ => {
+                let mut default: libc::sigaction = std::mem::zeroed();
+                default.sa_sigaction = libc::SIG_DFL;
+                libc::sigaction(sig, &default, std::ptr::null_mut());
+                libc::raise(sig);
+            }
+        }
     }
-
-    let mut file = file;
-    let pid = unsafe { libc::getpid() };
-    write!(file, "{}", pid)?;
-    
-    // Intentionally leak the file handle to maintain the OS lock 
-    // for the lifetime of the process.
-    std::mem::forget(file);
-
-    Ok(())
 }
 
-unsafe fn set_user(user: &User) -> DaemonResult<()> {
+/// Drops to `user`: permanently, or keeping root as the saved set-user-ID when `regainable`.
+unsafe fn set_user(user: &User, regainable: bool, set_env: bool) -> DaemonResult<()> {
     let cname = CString::new(user.0.as_str()).unwrap();
     let pwd = unsafe { libc::getpwnam(cname.as_ptr()) };
     if pwd.is_null() {
@@ -241,8 +2165,43 @@ unsafe fn set_user(user: &User) -> DaemonResult<()> {
             user.0
         )));
     }
+    let uid = unsafe { (*pwd).pw_uid };
+
+    if set_env {
+        // Copied out first: the next getpw* call may overwrite the static entry
+        let field = |ptr: *const libc::c_char| {
+            use std::os::unix::ffi::OsStrExt;
+            let bytes = if ptr.is_null() { &[][..] } else { unsafe { std::ffi::CStr::from_ptr(ptr) }.to_bytes() };
+            std::ffi::OsStr::from_bytes(bytes).to_os_string()
+        };
+        let (name, home, shell) = unsafe { (field((*pwd).pw_name), field((*pwd).pw_dir), field((*pwd).pw_shell)) };
+        let shell = if shell.is_empty() { "/bin/sh".into() } else { shell };
+        unsafe {
+            std::env::set_var("HOME", home);
+            std::env::set_var("SHELL", shell);
+            std::env::set_var("USER", &name);
+            std::env::set_var("LOGNAME", name);
+        }
+    }
+
+    if regainable {
+        // Real and effective IDs drop; the saved ID keeps root
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "openbsd"))]
+        let rc = unsafe { libc::setresuid(uid, uid, 0) };
+        // Without setresuid, keeping root as the real ID is the only way to keep it saved
+        #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "openbsd")))]
+        let rc = unsafe { libc::seteuid(uid) };
+        if rc < 0 {
+            return Err(DaemonError::PrivilegeError(format!(
+                "Failed to drop privileges (regainable): {}",
+                io::Error::last_os_error()
+            )));
+        }
+        DROPPED_UID.store(uid, std::sync::atomic::Ordering::Relaxed);
+        return Ok(());
+    }
 
-    if unsafe { libc::setuid((*pwd).pw_uid) } < 0 {
+    if unsafe { libc::setuid(uid) } < 0 {
         return Err(DaemonError::PrivilegeError(format!(
             "Failed to setuid: {}",
             io::Error::last_os_error()
@@ -251,6 +2210,35 @@ unsafe fn set_user(user: &User) -> DaemonResult<()> {
     Ok(())
 }
 
+/// User ID `privilege_regainable` dropped to; `u32::MAX` when privileges can't be regained.
+static DROPPED_UID: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(u32::MAX);
+
+/// Backs `daemon_forge::regain_privileges`.
+pub fn regain_privileges() -> DaemonResult<()> {
+    set_effective_uid(0)
+}
+
+/// Backs `daemon_forge::drop_privileges`.
+pub fn drop_privileges() -> DaemonResult<()> {
+    set_effective_uid(DROPPED_UID.load(std::sync::atomic::Ordering::Relaxed))
+}
+
+fn set_effective_uid(uid: libc::uid_t) -> DaemonResult<()> {
+    if DROPPED_UID.load(std::sync::atomic::Ordering::Relaxed) == u32::MAX {
+        return Err(DaemonError::PrivilegeError(
+            "Privileges were not dropped with privilege_regainable(true)".to_owned(),
+        ));
+    }
+    if unsafe { libc::seteuid(uid) } < 0 {
+        return Err(DaemonError::PrivilegeError(format!(
+            "Failed to seteuid({}): {}",
+            uid,
+            io::Error::last_os_error()
+        )));
+    }
+    Ok(())
+}
+
 unsafe fn set_group(group: &Group) -> DaemonResult<()> {
     let cname = CString::new(group.0.as_str()).unwrap();
     let grp = unsafe { libc::getgrnam(cname.as_ptr()) };
@@ -269,6 +2257,37 @@ unsafe fn set_group(group: &Group) -> DaemonResult<()> {
     Ok(())
 }
 
+/// Creates `dir` and its missing parents one level at a time, handing each directory
+/// created here to the target user/group and applying `mode`. Existing ones are left as is.
+unsafe fn create_owned_dir(
+    dir: &Path,
+    mode: u32,
+    user: &Option<User>,
+    group: &Option<Group>,
+) -> DaemonResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if dir.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = dir.parent().filter(|p| !p.as_os_str().is_empty()) {
+        unsafe { create_owned_dir(parent, mode, user, group)? };
+    }
+    match std::fs::create_dir(dir) {
+        Ok(()) => {}
+        // Created concurrently: not ours to re-own
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(()),
+        Err(e) => return Err(DaemonError::io("create directory", e)),
+    }
+    if user.is_some() || group.is_some() {
+        unsafe { apply_chown(dir, user, group)? };
+    }
+    // chmod after chown: changing ownership may clear the setgid bit
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(mode))
+        .map_err(|e| DaemonError::io("chmod directory", e))?;
+    Ok(())
+}
+
 unsafe fn apply_chown(path: &Path, user: &Option<User>, group: &Option<Group>) -> DaemonResult<()> {
     let uid = if let Some(u) = user {
         let cname = CString::new(u.0.as_str()).unwrap();
@@ -306,4 +2325,33 @@ unsafe fn apply_chown(path: &Path, user: &Option<User>, group: &Option<Group>) -
         )));
     }
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the process umask without changing it.
+    fn current_umask() -> libc::mode_t {
+        unsafe {
+            let mask = libc::umask(0);
+            libc::umask(mask);
+            mask
+        }
+    }
+
+    #[test]
+    fn socket_umask_is_restored_after_the_action() {
+        let original = unsafe { libc::umask(0o027) };
+
+        assert_eq!(with_umask(Some(0o117), current_umask), 0o117);
+        assert_eq!(current_umask(), 0o027);
+
+        // Also restored when the action fails
+        let result: DaemonResult<()> = with_umask(Some(0o117), || Err(DaemonError::ConfigError("x".to_owned())));
+        assert!(result.is_err());
+        assert_eq!(current_umask(), 0o027);
+
+        assert_eq!(with_umask(None, current_umask), 0o027);
+        unsafe { libc::umask(original) };
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 // =========================================================================
 // Compatibility Types (User/Group)
 // =========================================================================
@@ -13,6 +15,22 @@ pub struct User(String);
 #[allow(dead_code)]
 pub struct Group(String);
 
+#[cfg(not(unix))]
+impl User {
+    /// Accepts the name as-is; users are not resolved on Windows.
+    pub fn lookup(name: &str) -> crate::DaemonResult<Self> {
+        Ok(User(name.to_owned()))
+    }
+}
+
+#[cfg(not(unix))]
+impl Group {
+    /// Accepts the name as-is; groups are not resolved on Windows.
+    pub fn lookup(name: &str) -> crate::DaemonResult<Self> {
+        Ok(Group(name.to_owned()))
+    }
+}
+
 #[cfg(not(unix))]
 impl From<&str> for User {
     fn from(s: &str) -> Self {
@@ -44,6 +62,9 @@ pub use unix_types::{Group, User};
 
 #[cfg(unix)]
 mod unix_types {
+    use crate::error::{DaemonError, DaemonResult};
+    use std::ffi::CString;
+
     /// Represents a system user (Unix).
     #[derive(Debug, Clone)]
     pub struct User(pub String);
@@ -51,6 +72,35 @@ mod unix_types {
     #[derive(Debug, Clone)]
     pub struct Group(pub String);
 
+    // `TryFrom<&str>` can't be offered: the infallible `From<&str>` below already
+    // provides it through the standard library's blanket implementation.
+    impl User {
+        /// Resolves the user via `getpwnam` and fails with `PrivilegeError` if it doesn't exist.
+        ///
+        /// Use this to catch typos while still attached to the terminal, instead of
+        /// during the privilege drop after forking.
+        pub fn lookup(name: &str) -> DaemonResult<Self> {
+            let cname = CString::new(name)
+                .map_err(|_| DaemonError::PrivilegeError(format!("Invalid user name '{}'", name)))?;
+            if unsafe { libc::getpwnam(cname.as_ptr()) }.is_null() {
+                return Err(DaemonError::PrivilegeError(format!("User '{}' not found", name)));
+            }
+            Ok(User(name.to_owned()))
+        }
+    }
+
+    impl Group {
+        /// Resolves the group via `getgrnam` and fails with `PrivilegeError` if it doesn't exist.
+        pub fn lookup(name: &str) -> DaemonResult<Self> {
+            let cname = CString::new(name)
+                .map_err(|_| DaemonError::PrivilegeError(format!("Invalid group name '{}'", name)))?;
+            if unsafe { libc::getgrnam(cname.as_ptr()) }.is_null() {
+                return Err(DaemonError::PrivilegeError(format!("Group '{}' not found", name)));
+            }
+            Ok(Group(name.to_owned()))
+        }
+    }
+
     impl From<&str> for User {
         fn from(s: &str) -> Self {
             User(s.to_owned())
@@ -72,3 +122,127 @@ mod unix_types {
         }
     }
 }
+
+// =========================================================================
+// Environment
+// =========================================================================
+
+/// Resolution rule for keys set both explicitly (`env`) and through `inherit_env`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EnvPrecedence {
+    /// Values set with `env`/`env_opt` override inherited ones. Default.
+    #[default]
+    ExplicitWins,
+    /// Inherited values override those set with `env`/`env_opt`.
+    InheritedWins,
+}
+
+/// How the daemon process is created on Unix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RelaunchMode {
+    /// Classic double fork of the launching process. Default.
+    #[default]
+    Fork,
+    /// Spawns a fresh copy of `current_exe` (via `posix_spawn` where available) with an
+    /// internal flag in its environment; the copy detects the flag, detaches with
+    /// `setsid` and runs the setup. Safe when the launcher is multithreaded, like the
+    /// Windows backend. `launcher_linger` and `launcher_exit_on_child_failure` are
+    /// ignored in this mode.
+    Respawn,
+}
+
+// =========================================================================
+// Startup Profiling
+// =========================================================================
+
+/// Durations of the startup phases, recorded when `profile_startup(true)` is set.
+///
+/// A phase is `None` if it did not run (e.g. no `chroot` configured, or no fork in
+/// systemd/foreground mode).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StartupTimings {
+    /// Both `fork` calls of the detach sequence.
+    pub fork: Option<Duration>,
+    /// `setsid`.
+    pub setsid: Option<Duration>,
+    /// Changing into the working directory.
+    pub chdir: Option<Duration>,
+    /// `chroot` and the following `chdir("/")`.
+    pub chroot: Option<Duration>,
+    /// Locking and writing the PID file (and `chown_pid_file`).
+    pub pid_file: Option<Duration>,
+    /// The privileged action itself.
+    pub privileged_action: Option<Duration>,
+    /// Switching to the target group and user, including their lookups.
+    pub privilege_drop: Option<Duration>,
+}
+
+/// `SIGPIPE` disposition of the daemon, set with `ForgeDaemon::sigpipe`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SigpipeMode {
+    /// Ignored (`SIG_IGN`), as the Rust runtime sets it: writes to a closed pipe or
+    /// socket fail with `EPIPE`. Programs the daemon executes inherit the ignored signal. Default.
+    #[default]
+    Ignore,
+    /// Default disposition (`SIG_DFL`): writing to a closed pipe or socket terminates the daemon.
+    Default,
+    /// Caught by an empty handler: writes fail with `EPIPE` like `Ignore`, but programs
+    /// the daemon executes start with the default disposition.
+    Error,
+}
+
+/// (Unix) Kind of lock held on the PID (or lock) file, set with `ForgeDaemon::lock_style`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LockStyle {
+    /// BSD `flock`: owned by the open file description, so it follows the descriptor
+    /// across `fork` and survives other descriptors of the file being closed. Default.
+    #[default]
+    Flock,
+    /// POSIX record lock (`fcntl(F_SETLK)`), for filesystems where only those are
+    /// enforced (NFS). Owned by the process: it does not conflict with other locks
+    /// of the same process, and closing *any* descriptor of the file releases it.
+    Fcntl,
+}
+
+/// (Linux) IO scheduling class of the daemon, set with `ForgeDaemon::ionice`.
+///
+/// Priorities range from `0` (highest) to `7` (lowest); larger values are clamped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoClass {
+    /// Only gets disk time when no other process needs it.
+    Idle,
+    /// The kernel default class, with the given priority.
+    BestEffort(u8),
+    /// Served before every other class, with the given priority. Requires privileges.
+    Realtime(u8),
+}
+
+/// Which process returned from `ForgeDaemon::try_start`, and with what.
+#[derive(Debug)]
+pub enum StartState<T> {
+    /// The launching process, once the daemon is detached (and, with
+    /// `launcher_exit_on_child_failure`, set up). `start()` exits here instead.
+    Launcher {
+        /// PID of the daemon process.
+        child_pid: u32,
+    },
+    /// The daemon (or the foreground process under systemd or `container_aware`),
+    /// with the privileged action's output.
+    Daemon {
+        /// The privileged action's output.
+        setup: T,
+    },
+}
+
+/// Whether a daemon instance is running, as reported by `ForgeDaemon::status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DaemonStatus {
+    /// An instance is running, with this PID. Always known on Unix; on Windows `None`
+    /// if the global mutex exists but the PID file is missing or unreadable.
+    Running(Option<u32>),
+    /// No instance is running and no PID file is left behind.
+    Stopped,
+    /// The PID file exists but names no live process (Unix), or the global mutex
+    /// does not exist (Windows): the daemon died without cleaning up.
+    StalePidFile,
+}
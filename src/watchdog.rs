@@ -0,0 +1,66 @@
+//! In-process hang detection backing `ForgeDaemon::hang_watchdog` and `heartbeat()`.
+
+use std::fmt;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// What the watchdog does when no `heartbeat()` arrived within the timeout.
+pub enum WatchdogAction {
+    /// Aborts the process (`SIGABRT` on Unix), leaving a core dump if enabled.
+    Abort,
+    /// Calls the handler, then waits for another full timeout before firing again.
+    Call(Box<dyn FnMut() + Send>),
+}
+
+impl fmt::Debug for WatchdogAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchdogAction::Abort => f.write_str("Abort"),
+            WatchdogAction::Call(_) => f.write_str("Call(FnMut)"),
+        }
+    }
+}
+
+/// Reference point for `LAST_BEAT`.
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// Milliseconds since `EPOCH` at the last heartbeat.
+static LAST_BEAT: AtomicU64 = AtomicU64::new(0);
+
+fn now_millis() -> u64 {
+    EPOCH.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+/// Tells the `hang_watchdog` that the daemon is making progress.
+///
+/// Call it from every iteration of the main loop. Cheap enough for hot loops;
+/// a no-op when no watchdog is configured.
+pub fn heartbeat() {
+    LAST_BEAT.store(now_millis(), Ordering::Relaxed);
+}
+
+/// Starts the monitor thread. The first deadline counts from this call.
+pub(crate) fn spawn(timeout: Duration, mut action: WatchdogAction) {
+    heartbeat();
+    let timeout_ms = timeout.as_millis() as u64;
+    let poll = (timeout / 4).max(Duration::from_millis(10));
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(poll);
+
+        let silent_for = now_millis().saturating_sub(LAST_BEAT.load(Ordering::Relaxed));
+        if silent_for < timeout_ms {
+            continue;
+        }
+
+        eprintln!("{}", crate::daemon::critical_line(&format!("No heartbeat for {} ms; the daemon looks hung", silent_for)));
+        match &mut action {
+            WatchdogAction::Abort => std::process::abort(),
+            WatchdogAction::Call(handler) => {
+                handler();
+                heartbeat();
+            }
+        }
+    });
+}